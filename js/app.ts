@@ -1,12 +1,3 @@
-import Prism from "prismjs";
-import "prismjs/components/prism-bash";
-import "prismjs/components/prism-yaml";
-import "prismjs/components/prism-javascript";
-import "prismjs/components/prism-docker";
-import "prismjs/components/prism-nginx";
-import "prismjs/components/prism-elixir";
-import "prismjs/components/prism-css";
-
 const localizeTimeElements = () => {
   const timeElements: HTMLCollectionOf<HTMLTimeElement> =
     document.getElementsByTagName("time");
@@ -18,5 +9,4 @@ const localizeTimeElements = () => {
 
 window.addEventListener("DOMContentLoaded", () => {
   localizeTimeElements();
-  Prism.highlightAll(false);
 });
@@ -0,0 +1,439 @@
+use crate::{AppError, SharedState, pages::cache::not_modified};
+use anyhow::{Result, anyhow};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, Request, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, VARY},
+    },
+    response::{IntoResponse, Response},
+};
+use flate2::{Compression, write::GzEncoder};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs, io::Write, path, sync::Arc};
+use tower_http::services::ServeDir;
+use tracing::warn;
+
+/// Sent when the URL carries the asset's current digest, which can then never change.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Sent for undigested URLs, whose contents change in place.
+const REVALIDATE: &str = "no-cache";
+
+/// Text formats worth compressing, and so worth holding in memory. Images and fonts are compressed
+/// already and are served from disk instead.
+const COMPRESSIBLE: &[&str] = &[
+    "css", "js", "mjs", "map", "json", "svg", "txt", "xml", "html",
+];
+
+/// Served by its own route, straight from disk.
+const IMAGES: &str = "images";
+
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_WINDOW: u32 = 22;
+
+/// A representation of an asset in one content coding.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Encoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Identity => "identity",
+        }
+    }
+}
+
+/// A static text file held in memory, with the compressed variants that came out smaller.
+struct Asset {
+    digest: String,
+    content_type: HeaderValue,
+    identity: Bytes,
+    gzip: Option<Bytes>,
+    brotli: Option<Bytes>,
+}
+
+impl Asset {
+    fn new(name: &str, contents: Vec<u8>) -> Result<Self> {
+        let digest = hex::encode(&Sha256::digest(&contents)[..8]);
+        let content_type = HeaderValue::from_str(
+            mime_guess::from_path(name)
+                .first_or_octet_stream()
+                .essence_str(),
+        )?;
+
+        Ok(Self {
+            digest,
+            content_type,
+            gzip: smaller(&contents, gzip(&contents)?),
+            brotli: smaller(&contents, brotli(&contents)?),
+            identity: Bytes::from(contents),
+        })
+    }
+
+    /// Picks the smallest variant the client accepts. Brotli beats gzip whenever both exist, so
+    /// the client's q-values only matter for refusing an encoding.
+    fn negotiate(&self, accept_encoding: &str) -> (Encoding, Bytes) {
+        let variants = [
+            (Encoding::Brotli, &self.brotli),
+            (Encoding::Gzip, &self.gzip),
+        ];
+
+        variants
+            .into_iter()
+            .find_map(|(encoding, body)| {
+                body.as_ref()
+                    .filter(|_| accepts(accept_encoding, encoding))
+                    .map(|body| (encoding, body.clone()))
+            })
+            .unwrap_or((Encoding::Identity, self.identity.clone()))
+    }
+}
+
+fn gzip(contents: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(contents)?;
+    Ok(encoder.finish()?)
+}
+
+fn brotli(contents: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder =
+        brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+    encoder.write_all(contents)?;
+    encoder.flush()?;
+    Ok(encoder.into_inner())
+}
+
+/// Keeps a compressed variant only when it saves something.
+fn smaller(identity: &[u8], compressed: Vec<u8>) -> Option<Bytes> {
+    (compressed.len() < identity.len()).then(|| Bytes::from(compressed))
+}
+
+/// Whether `Accept-Encoding` allows `encoding`. A named entry overrides `*`, and either one is a
+/// refusal when its `q` is zero.
+fn accepts(accept_encoding: &str, encoding: Encoding) -> bool {
+    let mut wildcard = false;
+
+    for entry in accept_encoding.split(',') {
+        let mut parameters = entry.split(';');
+        let name = parameters.next().unwrap_or_default().trim();
+        let accepted = parameters
+            .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+            .all(|q| q.parse::<f32>().is_ok_and(|q| q > 0.0));
+
+        if name.eq_ignore_ascii_case(encoding.name()) {
+            return accepted;
+        }
+        if name == "*" {
+            wildcard = accepted;
+        }
+    }
+
+    wildcard
+}
+
+fn compressible(path: &path::Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| COMPRESSIBLE.contains(&extension))
+}
+
+/// The text files of the static directory, read and compressed once so that requests for them
+/// never touch the disk. Assets are keyed by their path relative to the directory, e.g.
+/// `app.css`. The `images` directory is left out since it is served on its own.
+#[derive(Default)]
+pub struct AssetManifest {
+    assets: HashMap<String, Asset>,
+}
+
+impl AssetManifest {
+    pub fn build(directory: &path::Path) -> Result<Self> {
+        let mut assets = HashMap::new();
+        let mut pending = vec![directory.to_path_buf()];
+
+        while let Some(current) = pending.pop() {
+            let entries = fs::read_dir(&current)
+                .map_err(|e| anyhow!("could not read {}: {e}", current.display()))?;
+
+            for entry in entries {
+                let entry = entry?;
+                let path = entry.path();
+                // Symlinks are not followed, since they could loop or lead out of the directory.
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    if path != directory.join(IMAGES) {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+                if !file_type.is_file() || !compressible(&path) {
+                    continue;
+                }
+
+                let name = path
+                    .strip_prefix(directory)?
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let contents = fs::read(&path)
+                    .map_err(|e| anyhow!("could not read {}: {e}", path.display()))?;
+
+                assets.insert(name.clone(), Asset::new(&name, contents)?);
+            }
+        }
+
+        Ok(Self { assets })
+    }
+
+    /// The URL of an asset with its digest in the query string, which is what lets it be cached
+    /// forever. Files left out of the manifest, and unknown ones, get a plain URL.
+    pub fn url(&self, name: &str) -> String {
+        match self.assets.get(name) {
+            Some(asset) => format!("/assets/{name}?v={}", asset.digest),
+            None => {
+                if compressible(path::Path::new(name)) {
+                    warn!("no static asset named {}", name);
+                }
+                format!("/assets/{name}")
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AssetQuery {
+    v: Option<String>,
+}
+
+/// Serves a file the manifest leaves out, such as an image or a font, from disk as it is.
+async fn serve_file(
+    state: &SharedState,
+    name: &str,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let mut request = Request::new(Body::empty());
+    *request.uri_mut() = format!("/{name}")
+        .parse()
+        .map_err(|_| AppError::PageNotFound)?;
+    *request.headers_mut() = headers;
+
+    let response = ServeDir::new(state.site.static_directory())
+        .try_call(request)
+        .await
+        .map_err(|e| anyhow!("could not read static asset {name}: {e}"))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(AppError::PageNotFound);
+    }
+
+    Ok(response.map(Body::new))
+}
+
+/// Serves a static asset in the best encoding the client accepts. URLs carrying the current digest
+/// are immutable; any other URL, including one with an outdated digest, must be revalidated.
+pub async fn serve(
+    Path(name): Path<String>,
+    Query(query): Query<AssetQuery>,
+    request: HeaderMap,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Response, AppError> {
+    let manifest = state.assets.load();
+    let Some(asset) = manifest.assets.get(&name) else {
+        return serve_file(&state, &name, request).await;
+    };
+
+    let accept_encoding = request
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let (encoding, body) = asset.negotiate(accept_encoding);
+
+    // Each encoding is a different representation, so each gets a tag of its own.
+    let etag = match encoding {
+        Encoding::Identity => format!("\"{}\"", asset.digest),
+        encoding => format!("\"{}-{}\"", asset.digest, encoding.name()),
+    };
+    let cache_control = if query.v.as_deref() == Some(asset.digest.as_str()) {
+        IMMUTABLE
+    } else {
+        REVALIDATE
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, asset.content_type.clone());
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+    headers.insert(
+        ETAG,
+        HeaderValue::try_from(&etag).map_err(|e| anyhow!("invalid etag for {name}: {e}"))?,
+    );
+    if encoding != Encoding::Identity {
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    }
+
+    if not_modified(&request, &etag, None) {
+        return Ok((StatusCode::NOT_MODIFIED, headers, Body::empty()).into_response());
+    }
+
+    Ok((headers, body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AssetManifest, AssetQuery, Encoding, accepts, serve};
+    use crate::{SharedState, pages::test_support::shared_state};
+    use arc_swap::ArcSwap;
+    use axum::{
+        extract::{Path, Query, State},
+        http::{
+            HeaderMap, HeaderValue, StatusCode,
+            header::{
+                ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH,
+            },
+        },
+    };
+    use std::{fs, os::unix::fs::symlink, sync::Arc};
+
+    fn create_shared_state() -> Arc<SharedState> {
+        let assets = AssetManifest::build(std::path::Path::new("static")).unwrap();
+
+        Arc::new(SharedState {
+            assets: ArcSwap::from_pointee(assets),
+            ..shared_state(Default::default(), Default::default())
+        })
+    }
+
+    fn headers(pairs: &[(axum::http::HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_accepts() {
+        assert!(accepts("gzip, deflate, br", Encoding::Brotli));
+        assert!(accepts("GZIP", Encoding::Gzip));
+        assert!(!accepts("gzip", Encoding::Brotli));
+        assert!(!accepts("br;q=0, gzip", Encoding::Brotli));
+        assert!(accepts("br;q=0.5", Encoding::Brotli));
+        assert!(accepts("*", Encoding::Gzip));
+        assert!(!accepts("*, gzip;q=0", Encoding::Gzip));
+        assert!(!accepts("", Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_manifest_holds_text_only() {
+        let manifest = AssetManifest::build(std::path::Path::new("static")).unwrap();
+
+        let favicon = &manifest.assets["favicon.svg"];
+        assert_eq!(favicon.content_type, "image/svg+xml");
+        assert!(favicon.brotli.is_some());
+        assert!(favicon.gzip.is_some());
+
+        assert!(
+            manifest
+                .assets
+                .keys()
+                .all(|name| !name.starts_with("images/"))
+        );
+
+        let url = manifest.url("favicon.svg");
+        assert_eq!(url, format!("/assets/favicon.svg?v={}", favicon.digest));
+    }
+
+    #[test]
+    fn test_manifest_skips_symlinks() {
+        let directory = std::env::temp_dir().join(format!("blog-assets-{}", std::process::id()));
+        let outside = directory.with_extension("outside");
+        fs::create_dir_all(directory.join("css")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(directory.join("css/app.css"), "body { color: black; }").unwrap();
+        fs::write(outside.join("secret.txt"), "not an asset").unwrap();
+        symlink(&directory, directory.join("css/loop")).unwrap();
+        symlink(&outside, directory.join("outside")).unwrap();
+        symlink(outside.join("secret.txt"), directory.join("secret.txt")).unwrap();
+
+        let manifest = AssetManifest::build(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+
+        let mut names: Vec<String> = manifest.unwrap().assets.into_keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["css/app.css"]);
+    }
+
+    #[tokio::test]
+    async fn test_serve_negotiates_and_caches() {
+        let state = create_shared_state();
+        let digest = state.assets.load().assets["favicon.svg"].digest.clone();
+
+        let response = serve(
+            Path("favicon.svg".to_string()),
+            Query(AssetQuery { v: Some(digest) }),
+            headers(&[(ACCEPT_ENCODING, "gzip, br")]),
+            State(state.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_ENCODING], "br");
+        assert_eq!(
+            response.headers()[CACHE_CONTROL],
+            "public, max-age=31536000, immutable"
+        );
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+
+        let response = serve(
+            Path("favicon.svg".to_string()),
+            Query(AssetQuery {
+                v: Some("outdated".to_string()),
+            }),
+            headers(&[(ACCEPT_ENCODING, "gzip"), (IF_NONE_MATCH, &etag)]),
+            State(state.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+
+        let response = serve(
+            Path("favicon.svg".to_string()),
+            Query(AssetQuery { v: None }),
+            headers(&[(ACCEPT_ENCODING, "br"), (IF_NONE_MATCH, &etag)]),
+            State(state.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let image = serve(
+            Path("images/009-proxy-diagram.png".to_string()),
+            Query(AssetQuery { v: None }),
+            headers(&[(ACCEPT_ENCODING, "gzip, br")]),
+            State(state.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(image.status(), StatusCode::OK);
+        assert_eq!(image.headers()[CONTENT_TYPE], "image/png");
+        assert!(image.headers().get(CONTENT_ENCODING).is_none());
+
+        let missing = serve(
+            Path("missing.css".to_string()),
+            Query(AssetQuery { v: None }),
+            HeaderMap::new(),
+            State(state),
+        )
+        .await;
+        assert!(missing.is_err());
+    }
+}
@@ -1,22 +1,33 @@
+use arc_swap::ArcSwap;
+use assets::AssetManifest;
 use axum::{
     Router,
     extract::Request,
     http::{HeaderValue, StatusCode, header::CONTENT_SECURITY_POLICY},
-    middleware::{Next, from_fn},
-    response::{Html, IntoResponse},
+    middleware::{Next, from_fn, from_fn_with_state},
+    response::IntoResponse,
     routing::get,
 };
-use pages::{PublicationManager, PublishedPages};
-use rust_web_common::{
-    telemetry::TelemetryBuilder,
-    templating::{Renderer, RendererError},
+use chrono::Utc;
+use pages::{
+    PublicationManager, PublishedPages, not_found::NotFound, og::CardCache, preview::PreviewSigner,
+};
+use rust_web_common::telemetry::TelemetryBuilder;
+use site::SiteConfig;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use templates::Templates;
+use tokio::{
+    join, process::Command, select, signal::unix::SignalKind, spawn, task::spawn_blocking,
+    time::sleep,
 };
-use std::sync::Arc;
-use tokio::{join, process::Command, select, signal::unix::SignalKind, spawn};
 use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing::{Instrument, debug, error, info, info_span, instrument};
 
+mod assets;
 mod pages;
+mod reload;
+mod site;
+mod templates;
 
 const CROSS_ORIGIN_OPENER_POLICY: &str = "Cross-Origin-Opener-Policy";
 
@@ -32,31 +43,16 @@ impl From<anyhow::Error> for AppError {
     }
 }
 
-impl From<RendererError> for AppError {
-    fn from(err: RendererError) -> Self {
-        error!("{:?}", err);
-        AppError::Unknown(err.into())
-    }
-}
-
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         match self {
-            AppError::PageNotFound => match Renderer::new("templates".to_string()) {
-                Ok(renderer) => match renderer.render("errors/404") {
-                    Ok(response) => (StatusCode::NOT_FOUND, Html(response)).into_response(),
-                    Err(_err) => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Something has gone wrong.",
-                    )
-                        .into_response(),
-                },
-                Err(_err) => (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Something has gone wrong.",
-                )
-                    .into_response(),
-            },
+            // The page itself is rendered by `pages::not_found::render`, which has the templates
+            // and the published pages to suggest from.
+            AppError::PageNotFound => {
+                let mut response = StatusCode::NOT_FOUND.into_response();
+                response.extensions_mut().insert(NotFound);
+                response
+            }
             AppError::Unknown(err) => {
                 error!("Unknown error: {}", err);
                 (
@@ -70,8 +66,12 @@ impl IntoResponse for AppError {
 }
 
 pub struct SharedState {
-    pub renderer: Renderer,
+    pub templates: ArcSwap<Templates>,
+    pub assets: ArcSwap<AssetManifest>,
     pub published_pages: Box<dyn PublicationManager>,
+    pub preview_signer: Option<PreviewSigner>,
+    pub site: SiteConfig,
+    pub og_cards: CardCache,
 }
 
 async fn shutdown_handler() {
@@ -81,6 +81,29 @@ async fn shutdown_handler() {
     signal.recv().await;
 }
 
+/// Upper bound on how long the scheduler sleeps, so newly scheduled pages are noticed even when
+/// nothing was waiting before.
+const SCHEDULER_MAX_WAIT: Duration = Duration::from_secs(60);
+
+async fn scheduled_publication_handler(state: Arc<SharedState>) {
+    loop {
+        let wait = match state.published_pages.snapshot().next_scheduled() {
+            Some(next) => (next - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+                .min(SCHEDULER_MAX_WAIT),
+            None => SCHEDULER_MAX_WAIT,
+        };
+
+        sleep(wait).await;
+
+        match state.published_pages.release_scheduled() {
+            0 => {}
+            released => info!(released, "published scheduled pages"),
+        }
+    }
+}
+
 async fn secure_headers(request: Request, next: Next) -> impl IntoResponse {
     let mut response = next.run(request).await;
     let headers = response.headers_mut();
@@ -103,24 +126,44 @@ async fn secure_headers(request: Request, next: Next) -> impl IntoResponse {
 }
 
 async fn server_handler(state: Arc<SharedState>) {
+    let static_directory = state.site.static_directory();
+
     let app = Router::new()
         .route("/", get(pages::home::build_response))
+        .route("/page/{number}", get(pages::home::page))
         .route("/post/{slug}/", get(pages::page::remove_slash))
         .route("/post/{slug}", get(pages::page::build_response))
+        .route("/preview/{slug}", get(pages::preview::build_response))
+        .route("/series/{series}", get(pages::series::show))
         .route("/sitemap.xml", get(pages::sitemap::build_response))
-        .nest_service("/assets", ServeDir::new("static").precompressed_gzip())
+        .route("/feed.xml", get(pages::feed::rss))
+        .route("/atom.xml", get(pages::feed::atom))
+        .route("/feed.json", get(pages::feed::json))
+        .route("/og/{file}", get(pages::og::build_response))
+        .route("/archive", get(pages::archive::index))
+        .route("/archive/{year}", get(pages::archive::year))
+        .route("/archive/{year}/{month}", get(pages::archive::month))
+        .route("/search", get(pages::search::page))
+        .route("/api/search", get(pages::search::api))
+        .route("/tags", get(pages::tags::index))
+        .route("/tags/{tag}", get(pages::tags::show))
+        .route("/assets/{*name}", get(assets::serve))
         .nest_service(
             "/images",
-            ServeDir::new("static/images").precompressed_gzip(),
+            ServeDir::new(static_directory.join("images")).precompressed_gzip(),
         )
+        .fallback(pages::not_found::fallback)
         .with_state(state.clone())
-        .fallback(|| async { Err::<StatusCode, AppError>(AppError::PageNotFound) })
+        .layer(from_fn_with_state(state.clone(), pages::not_found::render))
+        .layer(from_fn_with_state(state.clone(), pages::cache::conditional))
         .layer(from_fn(secure_headers))
         .layer(from_fn(metrics))
         .layer(TraceLayer::new_for_http())
         .route("/healthcheck", get(StatusCode::OK));
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
+    let listener = tokio::net::TcpListener::bind(state.site.bind_address())
+        .await
+        .unwrap();
 
     axum::serve(listener, app)
         .await
@@ -147,32 +190,72 @@ async fn metrics(request: Request, next: Next) -> impl IntoResponse {
 
 #[tokio::main]
 async fn main() {
+    let site = SiteConfig::load().expect("could not load site configuration");
+
+    let preview_signer = std::env::var("PREVIEW_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .map(PreviewSigner::new);
+
+    // `blog preview <slug>` prints a signed preview link for a draft instead of starting the server
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, command, slug] = args.as_slice()
+        && command == "preview"
+    {
+        match preview_signer {
+            Some(signer) => println!("{}", site.url(&signer.preview_path(slug))),
+            None => {
+                eprintln!("PREVIEW_SECRET must be set to sign preview links");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let mut telemetry = TelemetryBuilder::new("blog".to_string()).with_json_log_format();
     telemetry.init().expect("could not initialize subscriber");
 
-    spawn(compile_assets());
+    let compiled = spawn(compile_assets(site.static_directory().to_path_buf()));
 
-    let renderer = Renderer::new("templates".to_string()).unwrap();
-
-    let mut published_pages = PublishedPages::default();
+    let published_pages = PublishedPages::new(&site);
     published_pages
         .publish()
         .await
         .expect("Failed to publish pages during application startup");
 
+    // The manifest has to see the compiled assets, so it waits for them.
+    if let Err(err) = compiled.await {
+        error!("could not compile assets: {}", err);
+    }
+    // Compressing the assets is CPU-bound, so it stays off the runtime's threads.
+    let directory = site.static_directory().to_path_buf();
+    let assets = spawn_blocking(move || AssetManifest::build(&directory))
+        .await
+        .expect("could not load static assets")
+        .expect("could not load static assets");
+    let assets = Arc::new(assets);
+    let templates = Templates::load(&site, assets.clone()).expect("could not load templates");
+
     let shared_state = Arc::new(SharedState {
-        renderer,
+        templates: ArcSwap::from_pointee(templates),
+        assets: ArcSwap::new(assets),
         published_pages: Box::new(published_pages),
+        preview_signer,
+        site,
+        og_cards: CardCache::default(),
     });
 
+    spawn(reload::watch(shared_state.clone()));
+
     select! {
         _ = shutdown_handler() => {}
         _ = server_handler(shared_state.clone()) => {}
+        _ = scheduled_publication_handler(shared_state.clone()) => {}
     }
 }
 
 #[instrument]
-async fn compile_assets() {
+async fn compile_assets(static_directory: PathBuf) {
     if !cfg!(debug_assertions) {
         return;
     }
@@ -182,14 +265,14 @@ async fn compile_assets() {
         .arg("--input")
         .arg("css/app.css")
         .arg("--output")
-        .arg("static/app.css")
+        .arg(static_directory.join("app.css"))
         .output()
         .instrument(info_span!("compile css"));
 
     let js_command = Command::new("pnpm")
         .arg("esbuild")
         .arg("--bundle")
-        .arg("--outdir=static")
+        .arg(format!("--outdir={}", static_directory.display()))
         .arg("--sourcemap")
         .arg("--format=esm")
         .arg("js/app.ts")
@@ -1,18 +1,44 @@
+pub mod archive;
+pub mod cache;
+pub mod feed;
 pub mod home;
+pub mod not_found;
+pub mod og;
 pub mod page;
+pub mod preview;
+pub mod redirects;
+pub mod search;
+pub mod series;
 pub mod sitemap;
+pub mod tags;
+#[cfg(test)]
+pub mod test_support;
 
+use crate::site::SiteConfig;
 use anyhow::{Context, Result, anyhow};
+use arc_swap::ArcSwap;
 use chrono::{DateTime, Utc};
+use comrak::adapters::{HeadingAdapter, HeadingMeta};
 use comrak::html::escape;
+use comrak::nodes::{AstNode, NodeValue, Sourcepos};
 use comrak::options::Plugins;
-use comrak::{
-    Arena, Options, adapters::SyntaxHighlighterAdapter, nodes::NodeValue, parse_document,
-};
-use serde::Serialize;
-use std::borrow::Cow;
+use comrak::{Arena, Options, adapters::SyntaxHighlighterAdapter, parse_document};
+use search::{SearchIndex, SearchResult};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::borrow::{Borrow, Cow};
 use std::fmt::{self, Write};
-use std::{collections::HashMap, pin::Pin};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, LazyLock, Mutex},
+};
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
 use tokio::fs::{self, read_dir};
 use tracing::instrument;
 
@@ -20,209 +46,832 @@ use tracing::instrument;
 pub struct PublishedPage {
     pub published_at: DateTime<Utc>,
     pub path: String,
+    /// The Markdown body rendered to HTML once at publish time.
+    pub content: String,
     pub frontmatter: Frontmatter,
+    /// Headings of the body, nested by level. Empty when the frontmatter sets `toc: false`.
+    pub toc: Vec<TocEntry>,
+    pub reading_time: ReadingTime,
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl PublishedPage {
+    /// When the page last changed: its revision date, or its publication date if never revised.
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        self.frontmatter.revised_at.unwrap_or(self.published_at)
+    }
+}
+
+/// How long a page takes to read. Code blocks and frontmatter are not counted.
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct ReadingTime {
+    pub words: usize,
+    pub minutes: usize,
+}
+
+impl ReadingTime {
+    /// Estimates whole minutes at the given reading speed, rounding up so that short posts still
+    /// read as one minute.
+    fn new(words: usize, words_per_minute: usize) -> Self {
+        Self {
+            words,
+            minutes: words.div_ceil(words_per_minute.max(1)).max(1),
+        }
+    }
+}
+
+/// A heading linked from the table of contents. `id` is the anchor rendered on the heading.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TocEntry {
+    pub title: String,
+    pub id: String,
+    pub level: u8,
+    pub children: Vec<TocEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Frontmatter {
+    /// Former slugs, or former paths when they start with `/`, that redirect to this post.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Image shown when the post is shared, either a site path such as `/images/cover.png` or an
+    /// absolute URL.
+    #[serde(default)]
+    pub cover_image: Option<String>,
     pub description: String,
     pub preview: String,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
     pub published_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
     pub revised_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub series: Option<String>,
+    #[serde(default)]
+    pub series_order: Option<u32>,
     pub slug: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub title: String,
+    /// Set to `false` to hide the table of contents. Headings keep their anchors either way.
+    #[serde(default)]
+    pub toc: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub name: String,
+    pub slug: String,
+    /// Positions in [`Publication::pages`], newest first.
+    pub pages: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub name: String,
+    pub slug: String,
+    /// Positions in [`Publication::pages`], in reading order.
+    pub pages: Vec<usize>,
 }
 
+/// Everything produced by one publication run. It is never changed once built: handlers share it
+/// through an `Arc` for the length of a request, and publishing swaps in a new one. Scheduled
+/// pages have a future `published_at` and move into `pages` once that time passes. Pages are
+/// shared with the publications released from this one, and tags and series refer to them by
+/// position, so no page is ever held twice.
 #[derive(Default)]
+pub struct Publication {
+    drafts: Vec<Arc<PublishedPage>>,
+    /// Newest first.
+    pages: Vec<Arc<PublishedPage>>,
+    /// Positions in `pages` by slug.
+    slugs: HashMap<String, usize>,
+    scheduled: Vec<Arc<PublishedPage>>,
+    series: Vec<Series>,
+    tags: Vec<Tag>,
+    search: SearchIndex,
+    /// Entries from the redirects file, kept so the table can be rebuilt when pages are released.
+    site_redirects: BTreeMap<String, String>,
+    /// Old paths mapped to their new location.
+    redirects: HashMap<String, String>,
+}
+
+impl Publication {
+    /// `content.published` must already be ordered newest first.
+    fn new(content: Content, now: DateTime<Utc>) -> Self {
+        let (pages, scheduled) = content
+            .published
+            .into_iter()
+            .map(Arc::new)
+            .partition(|page| page.published_at <= now);
+
+        let mut publication = Publication {
+            drafts: content.drafts.into_iter().map(Arc::new).collect(),
+            pages,
+            scheduled,
+            site_redirects: content.redirects,
+            ..Default::default()
+        };
+        publication.index();
+        publication
+    }
+
+    /// A publication with every scheduled page that is due at `now` moved into the published set,
+    /// or `None` when nothing is due. The pages themselves are shared, not copied.
+    fn release(&self, now: DateTime<Utc>) -> Option<Self> {
+        let (due, scheduled): (Vec<_>, Vec<_>) = self
+            .scheduled
+            .iter()
+            .cloned()
+            .partition(|page| page.published_at <= now);
+
+        if due.is_empty() {
+            return None;
+        }
+
+        let mut pages = self.pages.clone();
+        pages.extend(due);
+        sort_newest_first(&mut pages);
+
+        let mut publication = Publication {
+            drafts: self.drafts.clone(),
+            pages,
+            scheduled,
+            site_redirects: self.site_redirects.clone(),
+            ..Default::default()
+        };
+        publication.index();
+        Some(publication)
+    }
+
+    fn index(&mut self) {
+        self.slugs = self
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(position, page)| (page.frontmatter.slug.clone(), position))
+            .collect();
+        self.series = collect_series(&self.pages);
+        self.tags = collect_tags(&self.pages);
+        self.search = SearchIndex::new(&self.pages);
+        self.redirects = redirects::collect(&self.pages, &self.site_redirects);
+    }
+
+    /// Published pages, newest first.
+    pub fn pages(&self) -> &[Arc<PublishedPage>] {
+        &self.pages
+    }
+
+    /// The pages at `positions`, such as those of a [`Tag`] or [`Series`].
+    pub fn pages_at<'a>(
+        &'a self,
+        positions: &'a [usize],
+    ) -> impl Iterator<Item = &'a PublishedPage> + 'a {
+        positions
+            .iter()
+            .map(|&position| self.pages[position].as_ref())
+    }
+
+    /// Where the page with `slug` sits in [`Publication::pages`].
+    pub fn position(&self, slug: &str) -> Option<usize> {
+        self.slugs.get(slug).copied()
+    }
+
+    pub fn get(&self, slug: &str) -> Option<&PublishedPage> {
+        self.position(slug)
+            .map(|position| self.pages[position].as_ref())
+    }
+
+    /// Looks up an unpublished page by slug. Drafts are only reachable through signed preview
+    /// links and never appear in `pages`. Scheduled pages can be previewed until they go live.
+    pub fn get_draft(&self, slug: &str) -> Option<&PublishedPage> {
+        self.drafts
+            .iter()
+            .chain(self.scheduled.iter())
+            .find(|draft| draft.frontmatter.slug == slug)
+            .map(Arc::as_ref)
+    }
+
+    pub fn series(&self) -> &[Series] {
+        &self.series
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    /// Full-text search over published pages, best match first.
+    pub fn search(&self, query: &str) -> Vec<SearchResult> {
+        self.search.search(query)
+    }
+
+    /// Where a moved path now lives, from post aliases and the redirects file.
+    pub fn get_redirect(&self, path: &str) -> Option<&str> {
+        self.redirects.get(path).map(String::as_str)
+    }
+
+    /// The most recent change across published pages, or `None` when there are none.
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.pages.iter().map(|page| page.updated_at()).max()
+    }
+
+    /// The earliest `published_at` among pages that are waiting to go live.
+    pub fn next_scheduled(&self) -> Option<DateTime<Utc>> {
+        self.scheduled.iter().map(|page| page.published_at).min()
+    }
+}
+
+/// Average adult reading speed for prose, used when none is configured.
+pub const DEFAULT_WORDS_PER_MINUTE: usize = 200;
+
 pub struct PublishedPages {
-    pages: Vec<PublishedPage>,
+    publication: ArcSwap<Publication>,
+    content_directory: PathBuf,
+    redirects_file: PathBuf,
+    words_per_minute: usize,
+}
+
+impl PublishedPages {
+    pub fn new(site: &SiteConfig) -> Self {
+        Self {
+            publication: ArcSwap::from_pointee(Publication::default()),
+            content_directory: site.content_directory().to_path_buf(),
+            redirects_file: site.redirects_file().to_path_buf(),
+            words_per_minute: site.words_per_minute(),
+        }
+    }
 }
 
 pub trait PublicationManager: Send + Sync {
-    fn get_all(&self) -> Result<Vec<PublishedPage>>;
+    /// The current publication. Reading it only clones an `Arc`, so it never copies pages or
+    /// waits on a publish, and it stays the same for as long as it is held.
+    fn snapshot(&self) -> Arc<Publication>;
 
-    fn publish<'f>(&'f mut self)
-    -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>>;
+    /// Publishes scheduled pages whose time has come, returning how many went live.
+    fn release_scheduled(&self) -> usize {
+        0
+    }
 
-    fn read<'f>(
-        &'f self,
-        path: &'f str,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + Sync + 'f>>;
+    /// Loads every content file and atomically replaces the published set. On error the previous
+    /// set stays in place.
+    fn publish<'f>(&'f self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>>;
 }
 
 impl PublicationManager for PublishedPages {
-    fn get_all(&self) -> Result<Vec<PublishedPage>> {
-        Ok(self.pages.clone())
+    fn snapshot(&self) -> Arc<Publication> {
+        self.publication.load_full()
     }
 
-    fn publish<'f>(
-        &'f mut self,
-    ) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>> {
+    fn release_scheduled(&self) -> usize {
+        let now = Utc::now();
+        let mut released = 0;
+
+        // Retried if a publish lands in between, so released pages are never lost to a reload.
+        self.publication.rcu(|current| match current.release(now) {
+            Some(next) => {
+                released = next.pages.len() - current.pages.len();
+                Arc::new(next)
+            }
+            None => {
+                released = 0;
+                Arc::clone(current)
+            }
+        });
+
+        released
+    }
+
+    fn publish<'f>(&'f self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>> {
         Box::pin(async {
-            let published_pages = published_pages().await?;
-            let length = published_pages.len();
+            let content = load_content(
+                &self.content_directory,
+                &self.redirects_file,
+                self.words_per_minute,
+            )
+            .await?;
+            let publication = Publication::new(content, Utc::now());
+            let length = publication.pages.len();
 
-            self.pages = published_pages;
+            self.publication.store(Arc::new(publication));
 
             Ok(length)
         })
     }
+}
 
-    fn read<'f>(
-        &'f self,
-        path: &'f str,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + Sync + 'f>> {
-        Box::pin(read_content_from_path(path))
-    }
+/// Converts a tag or series name such as `Mail server` into a URL segment like `mail-server`.
+pub fn slugify(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
 }
 
-#[instrument]
-async fn read_content_from_path(path: &str) -> Result<String> {
-    let content = fs::read(path)
-        .await
-        .map_err(|e| anyhow!("could not read file: {}", e))?;
-
-    String::from_utf8(content).map_err(|e| anyhow!("could not read file: {}", e))
-}
-
-impl Frontmatter {
-    #[instrument]
-    fn from_hashmap(map: HashMap<String, String>) -> Result<Self> {
-        let description = map
-            .get("description")
-            .context("missing description")?
-            .clone();
-        let preview = map.get("preview").context("missing preview")?.clone();
-        let slug = map.get("slug").context("missing slug")?.clone();
-        let title = map.get("title").context("missing title")?.clone();
-
-        let published_at = map
-            .get("published_at")
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc));
-
-        let revised_at = map
-            .get("revised_at")
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc));
-
-        Ok(Frontmatter {
-            description,
-            preview,
-            published_at,
-            revised_at,
-            slug,
-            title,
-        })
+/// Groups page positions by tag, keeping the newest-first page order within each tag. Tags that
+/// differ only by case or punctuation share a slug and are merged under the first spelling seen.
+fn collect_tags(pages: &[Arc<PublishedPage>]) -> Vec<Tag> {
+    let mut tags: BTreeMap<String, Tag> = BTreeMap::new();
+
+    for (position, page) in pages.iter().enumerate() {
+        for name in &page.frontmatter.tags {
+            let slug = slugify(name);
+            if slug.is_empty() {
+                continue;
+            }
+
+            let tag = tags.entry(slug.clone()).or_insert_with(|| Tag {
+                name: name.trim().to_string(),
+                slug,
+                pages: Vec::new(),
+            });
+
+            if tag.pages.last() != Some(&position) {
+                tag.pages.push(position);
+            }
+        }
     }
+
+    tags.into_values().collect()
 }
 
-#[instrument]
-fn frontmatter_to_hashmap(frontmatter_string: &str) -> Result<HashMap<String, String>> {
-    let frontmatter = frontmatter_string.to_string();
-    let frontmatter = frontmatter.replace("---\n", "").trim().to_string();
-    let frontmatter = frontmatter
-        .split("\n")
-        .filter_map(|a| {
-            Option::map(a.split_once(":"), |(key, value)| {
-                (key.trim().to_string(), value.trim().to_string())
+/// Groups page positions into series ordered by `series_order`. Parts without an order follow the
+/// numbered parts, oldest first.
+fn collect_series(pages: &[Arc<PublishedPage>]) -> Vec<Series> {
+    let mut series: BTreeMap<String, Series> = BTreeMap::new();
+
+    for (position, page) in pages.iter().enumerate() {
+        let Some(name) = &page.frontmatter.series else {
+            continue;
+        };
+
+        let slug = slugify(name);
+        if slug.is_empty() {
+            continue;
+        }
+
+        series
+            .entry(slug.clone())
+            .or_insert_with(|| Series {
+                name: name.trim().to_string(),
+                slug,
+                pages: Vec::new(),
             })
+            .pages
+            .push(position);
+    }
+
+    series
+        .into_values()
+        .map(|mut series| {
+            series.pages.sort_by_key(|&position| {
+                let page = &pages[position];
+                (
+                    page.frontmatter.series_order.unwrap_or(u32::MAX),
+                    page.published_at,
+                )
+            });
+            series
         })
-        .collect::<HashMap<String, String>>();
+        .collect()
+}
 
-    Ok(frontmatter)
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FrontmatterFormat {
+    Yaml,
+    Toml,
 }
 
-#[instrument]
-async fn extract_frontmatter_from_content_file(content: &str) -> Result<Frontmatter> {
-    let mut frontmatter = String::new();
-    let arena = Arena::new();
+impl FrontmatterFormat {
+    fn from_delimiter(line: &str) -> Option<Self> {
+        match line.trim_end() {
+            "---" => Some(FrontmatterFormat::Yaml),
+            "+++" => Some(FrontmatterFormat::Toml),
+            _ => None,
+        }
+    }
+}
 
-    let mut options = Options::default();
-    options.extension.front_matter_delimiter = Some(String::from("---"));
+struct SplitContent<'a> {
+    format: FrontmatterFormat,
+    frontmatter: &'a str,
+    body: &'a str,
+}
 
-    let nodes = parse_document(&arena, content, &options);
+/// Splits a content file into its raw frontmatter and Markdown body. YAML frontmatter is fenced by
+/// `---` lines and TOML frontmatter by `+++` lines, and the opening fence must be the first line.
+fn split_frontmatter(content: &str) -> Option<SplitContent<'_>> {
+    let (opening, rest) = content.split_once('\n')?;
+    let format = FrontmatterFormat::from_delimiter(opening)?;
 
-    for node in nodes.descendants() {
-        if let NodeValue::FrontMatter(ref mut a) = node.data.borrow_mut().value {
-            frontmatter = a.clone();
-            *a = String::new();
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if FrontmatterFormat::from_delimiter(line) == Some(format) {
+            return Some(SplitContent {
+                format,
+                frontmatter: &rest[..offset],
+                body: &rest[offset + line.len()..],
+            });
         }
+        offset += line.len();
     }
 
-    let frontmatter = frontmatter_to_hashmap(&frontmatter)?;
-    Frontmatter::from_hashmap(frontmatter)
+    None
+}
+
+/// Builds a diagnostic for a frontmatter error. `line` is relative to the frontmatter, so it is
+/// shifted past the opening fence to point at the line in the content file.
+fn frontmatter_error(
+    path: &str,
+    frontmatter: &str,
+    line: Option<usize>,
+    message: &str,
+) -> anyhow::Error {
+    let Some(line) = line else {
+        return anyhow!("{path}: invalid frontmatter: {message}");
+    };
+
+    // Serde does not always name the field, e.g. for a malformed TOML date, so fall back to the
+    // key on the offending line.
+    let field = frontmatter
+        .lines()
+        .nth(line.saturating_sub(1))
+        .and_then(|text| text.split_once([':', '=']))
+        .map(|(key, _)| key.trim())
+        .filter(|key| {
+            !key.is_empty()
+                && !message.contains("field `")
+                && !message.starts_with(&format!("{key}:"))
+        });
+
+    match field {
+        Some(field) => anyhow!(
+            "{path}:{}: invalid frontmatter field `{field}`: {message}",
+            line + 1
+        ),
+        None => anyhow!("{path}:{}: invalid frontmatter: {message}", line + 1),
+    }
+}
+
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct TimestampVisitor;
+
+    impl<'de> Visitor<'de> for TimestampVisitor {
+        type Value = Option<DateTime<Utc>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an RFC 3339 timestamp")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(TimestampVisitor)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            DateTime::parse_from_rfc3339(value)
+                .map(|dt| Some(dt.with_timezone(&Utc)))
+                .map_err(|e| E::custom(format!("invalid timestamp `{value}`: {e}")))
+        }
+
+        // TOML datetimes are handed to serde as a single-entry map holding the original text.
+        fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+            let (_, value) = map
+                .next_entry::<String, String>()?
+                .ok_or_else(|| de::Error::custom("empty timestamp"))?;
+
+            self.visit_str(&value)
+        }
+    }
+
+    deserializer.deserialize_option(TimestampVisitor)
 }
 
 #[instrument]
-async fn without_frontmatter(content: &str) -> Result<String> {
-    let arena = Arena::new();
-    let mut options = Options::default();
-    options.extension.front_matter_delimiter = Some(String::from("---"));
+fn parse_frontmatter(path: &str, content: &str) -> Result<Frontmatter> {
+    let split = split_frontmatter(content)
+        .ok_or_else(|| anyhow!("{path}: missing `---` or `+++` frontmatter block"))?;
 
-    let mut plugins = Plugins::default();
-    plugins.render.codefence_syntax_highlighter = Some(&(SyntaxAdapter {}));
+    match split.format {
+        FrontmatterFormat::Yaml => serde_yaml::from_str(split.frontmatter).map_err(|e| {
+            let line = e.location().map(|location| location.line());
+            let message = match e.location() {
+                Some(location) => e
+                    .to_string()
+                    .trim_end_matches(&format!(
+                        " at line {} column {}",
+                        location.line(),
+                        location.column()
+                    ))
+                    .to_string(),
+                None => e.to_string(),
+            };
+
+            frontmatter_error(path, split.frontmatter, line, &message)
+        }),
+        FrontmatterFormat::Toml => toml::from_str(split.frontmatter).map_err(|e| {
+            let line = e
+                .span()
+                .map(|span| split.frontmatter[..span.start].matches('\n').count() + 1);
 
-    let nodes = parse_document(&arena, content, &options);
+            frontmatter_error(path, split.frontmatter, line, e.message())
+        }),
+    }
+}
+
+/// The HTML of a Markdown body along with the headings found in it.
+struct Rendered {
+    html: String,
+    toc: Vec<TocEntry>,
+    words: usize,
+}
 
+/// Hands out heading IDs based on the heading text, suffixing repeats with `-1`, `-2`, and so on
+/// so that every anchor on a page is unique.
+#[derive(Default)]
+struct HeadingIds {
+    issued: HashSet<String>,
+}
+
+impl HeadingIds {
+    fn next(&mut self, title: &str) -> String {
+        let mut base = slugify(title);
+        if base.is_empty() {
+            base = "section".to_string();
+        }
+
+        let mut id = base.clone();
+        let mut suffix = 0;
+        while !self.issued.insert(id.clone()) {
+            suffix += 1;
+            id = format!("{base}-{suffix}");
+        }
+
+        id
+    }
+}
+
+fn heading_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for descendant in node.descendants() {
+        match &descendant.data.borrow().value {
+            NodeValue::Text(literal) => text.push_str(literal),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Counts the words a reader reads. Code blocks and raw HTML are skipped, while inline code
+/// counts since it usually reads as part of the sentence.
+fn count_words<'a>(root: &'a AstNode<'a>) -> usize {
+    root.descendants()
+        .map(|node| match &node.data.borrow().value {
+            NodeValue::Text(literal) => literal.split_whitespace().count(),
+            NodeValue::Code(code) => code.literal.split_whitespace().count(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Nests a flat list of headings so that each one holds the deeper headings that follow it.
+fn nest_headings(headings: Vec<TocEntry>) -> Vec<TocEntry> {
+    let mut stack: Vec<TocEntry> = Vec::new();
+    let mut toc = Vec::new();
+
+    for heading in headings {
+        while stack.last().is_some_and(|last| last.level >= heading.level) {
+            let finished = stack.pop().expect("stack is not empty");
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(finished),
+                None => toc.push(finished),
+            }
+        }
+        stack.push(heading);
+    }
+
+    while let Some(finished) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(finished),
+            None => toc.push(finished),
+        }
+    }
+
+    toc
+}
+
+/// Writes heading tags with the IDs issued while walking the AST. The renderer calls it once for
+/// every heading node in document order, which is the order the IDs were issued in.
+struct HeadingAnchors {
+    ids: Mutex<VecDeque<String>>,
+}
+
+impl HeadingAdapter for HeadingAnchors {
+    fn enter(
+        &self,
+        output: &mut dyn Write,
+        heading: &HeadingMeta,
+        sourcepos: Option<Sourcepos>,
+    ) -> fmt::Result {
+        write!(output, "<h{}", heading.level)?;
+        if let Some(id) = self.ids.lock().map_err(|_| fmt::Error)?.pop_front() {
+            write!(output, " id=\"{id}\"")?;
+        }
+        if let Some(sourcepos) = sourcepos {
+            write!(output, " data-sourcepos=\"{sourcepos}\"")?;
+        }
+        output.write_str(">")
+    }
+
+    fn exit(&self, output: &mut dyn Write, heading: &HeadingMeta) -> fmt::Result {
+        writeln!(output, "</h{}>", heading.level)
+    }
+}
+
+#[instrument(skip(content))]
+fn render_markdown(content: &str) -> Result<Rendered> {
+    let arena = Arena::new();
+    let options = Options::default();
+
+    let body = split_frontmatter(content).map_or(content, |split| split.body);
+    let nodes = parse_document(&arena, body, &options);
+
+    let mut ids = HeadingIds::default();
+    let mut headings = Vec::new();
     for node in nodes.descendants() {
-        if let NodeValue::FrontMatter(ref mut a) = node.data.borrow_mut().value {
-            *a = String::new();
+        if let NodeValue::Heading(heading) = node.data.borrow().value {
+            let title = heading_text(node);
+            headings.push(TocEntry {
+                id: ids.next(&title),
+                title,
+                level: heading.level,
+                children: Vec::new(),
+            });
         }
     }
 
+    let anchors = HeadingAnchors {
+        ids: Mutex::new(headings.iter().map(|heading| heading.id.clone()).collect()),
+    };
+    let mut plugins = Plugins::default();
+    plugins.render.codefence_syntax_highlighter = Some(&SyntaxAdapter);
+    plugins.render.heading_adapter = Some(&anchors);
+
     let mut html = String::new();
     comrak::format_html_with_plugins(nodes, &options, &mut html, &plugins)?;
-    Ok(html)
+
+    Ok(Rendered {
+        html,
+        toc: nest_headings(headings),
+        words: count_words(nodes),
+    })
+}
+
+fn sort_newest_first<P: Borrow<PublishedPage>>(pages: &mut [P]) {
+    pages.sort_by(|a, b| {
+        b.borrow()
+            .published_at
+            .timestamp_micros()
+            .cmp(&a.borrow().published_at.timestamp_micros())
+    });
+}
+
+#[derive(Default)]
+struct Content {
+    published: Vec<PublishedPage>,
+    drafts: Vec<PublishedPage>,
+    redirects: BTreeMap<String, String>,
 }
 
 #[instrument]
-async fn published_pages() -> Result<Vec<PublishedPage>> {
-    let mut content_files = read_dir("./content").await?;
+async fn load_content(
+    content_directory: &Path,
+    redirects_file: &Path,
+    words_per_minute: usize,
+) -> Result<Content> {
+    let mut content_files = read_dir(content_directory)
+        .await
+        .with_context(|| format!("could not read {}", content_directory.display()))?;
 
     let mut published_pages: Vec<PublishedPage> = Vec::new();
+    let mut drafts: Vec<PublishedPage> = Vec::new();
 
     while let Some(content_file) = content_files.next_entry().await? {
         let content = fs::read(content_file.path()).await?;
         let content = String::from_utf8(content)?;
-        let frontmatter = extract_frontmatter_from_content_file(&content).await?;
+        let path = content_file
+            .path()
+            .to_str()
+            .ok_or(anyhow!("could not extract path as string"))?
+            .to_string();
+        let frontmatter = parse_frontmatter(&path, &content)?;
+        let rendered = render_markdown(&content)
+            .map_err(|e| anyhow!("{path}: could not render markdown: {e}"))?;
+        let content = rendered.html;
+        let toc = match frontmatter.toc {
+            Some(false) => Vec::new(),
+            _ => rendered.toc,
+        };
+        let reading_time = ReadingTime::new(rendered.words, words_per_minute);
 
         match frontmatter.published_at {
-            None => {}
+            // Drafts have no publication date yet, so previews show them as if published now.
+            None => drafts.push(PublishedPage {
+                published_at: Utc::now(),
+                path,
+                content,
+                frontmatter,
+                toc,
+                reading_time,
+            }),
             Some(published_at) => {
                 published_pages.push(PublishedPage {
                     published_at,
-                    path: content_file
-                        .path()
-                        .to_str()
-                        .ok_or(anyhow!("could not extract path as string"))?
-                        .to_string(),
+                    path,
+                    content,
                     frontmatter,
+                    toc,
+                    reading_time,
                 });
             }
         }
     }
 
-    published_pages.sort_by(|a, b| {
-        b.published_at
-            .timestamp_micros()
-            .cmp(&a.published_at.timestamp_micros())
-    });
+    sort_newest_first(&mut published_pages);
+
+    let redirects = redirects::load(redirects_file).await?;
+    redirects::validate(&published_pages, redirects_file, &redirects)?;
+
+    Ok(Content {
+        published: published_pages,
+        drafts,
+        redirects,
+    })
+}
 
-    Ok(published_pages)
+/// Loading the syntax definitions takes a noticeable amount of time, so it happens once on first
+/// use and is shared by every publication run.
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(two_face::syntax::extra_newlines);
+
+/// Maps fence labels used in our posts that are neither a syntax name nor a file extension.
+fn syntax_token(lang: &str) -> &str {
+    match lang {
+        "docker" => "Dockerfile",
+        "plaintext" | "text" => "txt",
+        "console" | "shell" => "bash",
+        lang => lang,
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct SyntaxAdapter;
 
 impl SyntaxHighlighterAdapter for SyntaxAdapter {
+    /// Highlights code into spans classed by scope, e.g. `hl-keyword hl-control`, and leaves the
+    /// colors to `css/highlight.css`. Unknown languages are only escaped.
     fn write_highlighted(
         &self,
         output: &mut dyn Write,
-        _lang: Option<&str>,
+        lang: Option<&str>,
         code: &str,
     ) -> fmt::Result {
-        escape(output, code)
+        let syntax_set = &*SYNTAX_SET;
+        let Some(syntax) =
+            lang.and_then(|lang| syntax_set.find_syntax_by_token(syntax_token(lang)))
+        else {
+            return escape(output, code);
+        };
+
+        let mut generator = ClassedHTMLGenerator::new_with_class_style(
+            syntax,
+            syntax_set,
+            ClassStyle::SpacedPrefixed { prefix: "hl-" },
+        );
+
+        for line in LinesWithEndings::from(code) {
+            if generator
+                .parse_html_for_line_which_includes_newline(line)
+                .is_err()
+            {
+                return escape(output, code);
+            }
+        }
+
+        output.write_str(&generator.finalize())
     }
 
     fn write_pre_tag(
@@ -241,7 +890,7 @@ impl SyntaxHighlighterAdapter for SyntaxAdapter {
 
         write!(
             output,
-            "<pre class=\"not-prose\" lang=\"{}\">",
+            "<pre class=\"not-prose highlight\" lang=\"{}\">",
             escaped_lang
         )
     }
@@ -263,3 +912,328 @@ impl SyntaxHighlighterAdapter for SyntaxAdapter {
         output.write_str(&format!("<code class=\"{}\">", escaped_class))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Content, Frontmatter, HeadingIds, Publication, PublishedPage, ReadingTime, SYNTAX_SET,
+        SyntaxAdapter, TocEntry, collect_series, collect_tags, parse_frontmatter, render_markdown,
+        slugify, syntax_token,
+    };
+    use chrono::{DateTime, Duration, Utc};
+    use comrak::adapters::SyntaxHighlighterAdapter;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_yaml_frontmatter() {
+        let content = "---\nslug: yaml\ntitle: \"Quoted: with a colon\"\npreview: >\n  Folded across\n  two lines\ndescription: plain\npublished_at: 2023-01-01T00:00:00+00:00\n---\n\nBody";
+        let frontmatter = parse_frontmatter("content/yaml.md", content).unwrap();
+
+        assert_eq!(frontmatter.title, "Quoted: with a colon");
+        assert_eq!(frontmatter.preview, "Folded across two lines\n");
+        assert_eq!(
+            frontmatter.published_at,
+            Some(
+                DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+        assert_eq!(frontmatter.revised_at, None);
+    }
+
+    #[test]
+    fn test_parse_toml_frontmatter() {
+        let content = "+++\nslug = \"toml\"\ntitle = \"TOML\"\npreview = \"preview\"\ndescription = \"description\"\npublished_at = 2023-01-01T00:00:00Z\nrevised_at = \"2023-01-15T00:00:00+00:00\"\n+++\n\nBody";
+        let frontmatter = parse_frontmatter("content/toml.md", content).unwrap();
+
+        assert_eq!(frontmatter.slug, "toml");
+        assert!(frontmatter.published_at.is_some());
+        assert!(frontmatter.revised_at.is_some());
+    }
+
+    #[test]
+    fn test_parse_frontmatter_invalid_date_names_file_line_and_field() {
+        let content = "---\nslug: bad\ntitle: Bad\npreview: preview\ndescription: description\npublished_at: yesterday\n---\n";
+        let error = parse_frontmatter("content/bad.md", content)
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.starts_with("content/bad.md:6:"), "{error}");
+        assert!(error.contains("published_at"), "{error}");
+    }
+
+    #[test]
+    fn test_parse_frontmatter_unknown_key() {
+        let content = "+++\nslug = \"bad\"\ntitle = \"Bad\"\npreview = \"preview\"\ndescription = \"description\"\nauthor = \"someone\"\n+++\n";
+        let error = parse_frontmatter("content/bad.md", content)
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.starts_with("content/bad.md:6:"), "{error}");
+        assert!(error.contains("author"), "{error}");
+    }
+
+    #[test]
+    fn test_parse_frontmatter_missing_block() {
+        assert!(parse_frontmatter("content/none.md", "# Just markdown").is_err());
+    }
+
+    #[test]
+    fn test_render_markdown_strips_toml_block() {
+        let html = render_markdown("+++\ntitle = \"Hidden\"\n+++\n\nVisible")
+            .unwrap()
+            .html;
+
+        assert!(!html.contains("Hidden"));
+        assert!(html.contains("Visible"));
+    }
+
+    #[test]
+    fn test_render_markdown_adds_heading_ids() {
+        let rendered =
+            render_markdown("## Install `dovecot`\n\ntext\n\n## Setup\n\n## Setup\n").unwrap();
+
+        assert!(rendered.html.contains("<h2 id=\"install-dovecot\">"));
+        assert!(rendered.html.contains("<h2 id=\"setup\">"));
+        assert!(rendered.html.contains("<h2 id=\"setup-1\">"));
+    }
+
+    #[test]
+    fn test_render_markdown_keeps_inline_markup_in_headings() {
+        let rendered = render_markdown("# Title\n\n### This is **bold**\n").unwrap();
+
+        assert!(rendered.html.contains("<h1 id=\"title\">Title</h1>"));
+        assert!(
+            rendered
+                .html
+                .contains("<h3 id=\"this-is-bold\">This is <strong>bold</strong></h3>")
+        );
+    }
+
+    #[test]
+    fn test_render_markdown_nests_toc() {
+        let rendered =
+            render_markdown("## First\n\n### Child\n\n#### Grandchild\n\n## Second\n").unwrap();
+
+        let titles = |entries: &[TocEntry]| -> Vec<String> {
+            entries.iter().map(|entry| entry.title.clone()).collect()
+        };
+
+        assert_eq!(titles(&rendered.toc), vec!["First", "Second"]);
+        assert_eq!(titles(&rendered.toc[0].children), vec!["Child"]);
+        assert_eq!(
+            titles(&rendered.toc[0].children[0].children),
+            vec!["Grandchild"]
+        );
+        assert!(rendered.toc[1].children.is_empty());
+    }
+
+    #[test]
+    fn test_render_markdown_counts_words_outside_code_blocks() {
+        let rendered = render_markdown(
+            "---\ntitle: Not counted\n---\n\n# Two words\n\nRun `kubectl apply` now.\n\n```bash\necho not counted\n```\n",
+        )
+        .unwrap();
+
+        assert_eq!(rendered.words, 6);
+    }
+
+    #[test]
+    fn test_reading_time_rounds_up_to_whole_minutes() {
+        assert_eq!(ReadingTime::new(0, 200).minutes, 1);
+        assert_eq!(ReadingTime::new(200, 200).minutes, 1);
+        assert_eq!(ReadingTime::new(201, 200).minutes, 2);
+        assert_eq!(ReadingTime::new(900, 300).minutes, 3);
+    }
+
+    #[test]
+    fn test_heading_ids_do_not_collide_with_suffixed_titles() {
+        let mut ids = HeadingIds::default();
+
+        assert_eq!(ids.next("Setup"), "setup");
+        assert_eq!(ids.next("Setup 1"), "setup-1");
+        assert_eq!(ids.next("Setup"), "setup-2");
+        assert_eq!(ids.next("!!!"), "section");
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Mail server"), "mail-server");
+        assert_eq!(slugify(" 3D printing "), "3d-printing");
+        assert_eq!(slugify("Next.js"), "next-js");
+    }
+
+    #[test]
+    fn test_collect_tags_groups_pages_by_slug() {
+        let page = |slug: &str, tags: &[&str]| PublishedPage {
+            path: slug.to_string(),
+            content: String::new(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                slug: slug.to_string(),
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                ..Default::default()
+            },
+        };
+        let pages = vec![
+            Arc::new(page("newer", &["Kubernetes", "Mail server"])),
+            Arc::new(page("older", &["kubernetes"])),
+            Arc::new(page("untagged", &[])),
+        ];
+
+        let tags = collect_tags(&pages);
+
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].name, "Kubernetes");
+        assert_eq!(
+            tags[0]
+                .pages
+                .iter()
+                .map(|&position| pages[position].frontmatter.slug.as_str())
+                .collect::<Vec<_>>(),
+            vec!["newer", "older"]
+        );
+        assert_eq!(tags[1].slug, "mail-server");
+    }
+
+    #[test]
+    fn test_collect_series_orders_parts() {
+        let page = |slug: &str, series: Option<&str>, series_order: Option<u32>| PublishedPage {
+            path: slug.to_string(),
+            content: String::new(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                series: series.map(str::to_string),
+                series_order,
+                slug: slug.to_string(),
+                ..Default::default()
+            },
+        };
+        let pages = vec![
+            Arc::new(page("part-two", Some("Mail server"), Some(2))),
+            Arc::new(page("standalone", None, None)),
+            Arc::new(page("unordered", Some("Mail server"), None)),
+            Arc::new(page("part-one", Some("Mail server"), Some(1))),
+        ];
+
+        let series = collect_series(&pages);
+
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].slug, "mail-server");
+        assert_eq!(
+            series[0]
+                .pages
+                .iter()
+                .map(|&position| pages[position].frontmatter.slug.as_str())
+                .collect::<Vec<_>>(),
+            vec!["part-one", "part-two", "unordered"]
+        );
+    }
+
+    #[test]
+    fn test_publication_holds_back_future_pages() {
+        let now = Utc::now();
+        let page = |slug: &str, published_at: DateTime<Utc>| PublishedPage {
+            path: slug.to_string(),
+            content: String::new(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at,
+            frontmatter: Frontmatter {
+                published_at: Some(published_at),
+                slug: slug.to_string(),
+                tags: vec!["Kubernetes".to_string()],
+                ..Default::default()
+            },
+        };
+        let content = Content {
+            published: vec![
+                page("later", now + Duration::hours(2)),
+                page("soon", now + Duration::hours(1)),
+                page("past", now - Duration::hours(1)),
+            ],
+            drafts: vec![],
+            redirects: Default::default(),
+        };
+
+        let publication = Publication::new(content, now);
+        let slugs = |publication: &Publication| {
+            publication
+                .pages
+                .iter()
+                .map(|p| p.frontmatter.slug.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(slugs(&publication), vec!["past"]);
+        assert_eq!(publication.scheduled.len(), 2);
+        assert_eq!(publication.tags[0].pages.len(), 1);
+
+        assert!(publication.release(now).is_none());
+        let released = publication.release(now + Duration::minutes(90)).unwrap();
+        // Released publications share the pages they already had rather than copying them.
+        assert!(Arc::ptr_eq(&publication.pages[0], &released.pages[1]));
+        let publication = released;
+        assert_eq!(slugs(&publication), vec!["soon", "past"]);
+        assert_eq!(publication.tags[0].pages.len(), 2);
+        assert_eq!(publication.position("past"), Some(1));
+
+        let publication = publication.release(now + Duration::hours(3)).unwrap();
+        assert_eq!(slugs(&publication), vec!["later", "soon", "past"]);
+        assert_eq!(publication.get("past").unwrap().path, "past");
+        assert!(publication.scheduled.is_empty());
+    }
+
+    fn highlight(lang: Option<&str>, code: &str) -> String {
+        let mut html = String::new();
+        SyntaxAdapter
+            .write_highlighted(&mut html, lang, code)
+            .unwrap();
+        html
+    }
+
+    #[test]
+    fn test_highlight_emits_scope_classes() {
+        let html = highlight(Some("rust"), "fn main() {\n    let x = \"<b>\";\n}\n");
+
+        assert!(html.contains("hl-keyword"), "{html}");
+        assert!(html.contains("hl-string"), "{html}");
+        assert!(html.contains("&lt;b&gt;"), "{html}");
+        assert!(!html.contains("<b>"), "{html}");
+    }
+
+    #[test]
+    fn test_highlight_unknown_language_is_escaped() {
+        assert_eq!(highlight(Some("not-a-language"), "<a>"), "&lt;a&gt;");
+        assert_eq!(highlight(None, "a & b"), "a &amp; b");
+    }
+
+    #[test]
+    fn test_highlight_supports_languages_used_in_posts() {
+        for lang in [
+            "bash",
+            "css",
+            "docker",
+            "elixir",
+            "javascript",
+            "nginx",
+            "plaintext",
+            "rust",
+            "toml",
+            "typescript",
+            "yaml",
+        ] {
+            assert!(
+                SYNTAX_SET
+                    .find_syntax_by_token(syntax_token(lang))
+                    .is_some(),
+                "{lang}"
+            );
+        }
+    }
+}
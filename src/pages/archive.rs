@@ -0,0 +1,319 @@
+use super::PublishedPage;
+use crate::{AppError, SharedState, templates::Head};
+use axum::{
+    extract::{Path, State},
+    response::Html,
+};
+use chrono::{DateTime, Datelike, Utc};
+use serde::Serialize;
+use std::{cmp::Reverse, collections::BTreeMap, sync::Arc};
+
+#[derive(Serialize, Debug)]
+struct Link {
+    title: String,
+    slug: String,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+struct Month {
+    year: i32,
+    month: u32,
+    name: String,
+    path: String,
+    count: usize,
+    pages: Vec<Link>,
+}
+
+#[derive(Serialize, Debug)]
+struct Year {
+    year: i32,
+    count: usize,
+    months: Vec<Month>,
+}
+
+/// The context of `pages/archive`.
+#[derive(Serialize, Debug)]
+struct ArchiveContext {
+    years: Vec<Year>,
+    count: usize,
+}
+
+/// Groups pages by the year and month of `published_at`, newest period first. Pages keep their
+/// newest-first order within a month.
+fn group(pages: &[Arc<PublishedPage>]) -> Vec<Year> {
+    let mut periods: BTreeMap<Reverse<i32>, BTreeMap<Reverse<u32>, Vec<&PublishedPage>>> =
+        BTreeMap::new();
+
+    for page in pages {
+        let year = page.published_at.year();
+        let month = page.published_at.month();
+        periods
+            .entry(Reverse(year))
+            .or_default()
+            .entry(Reverse(month))
+            .or_default()
+            .push(page);
+    }
+
+    periods
+        .into_iter()
+        .map(|(Reverse(year), months)| {
+            let months: Vec<Month> = months
+                .into_iter()
+                .map(|(Reverse(month), pages)| Month {
+                    year,
+                    month,
+                    name: pages[0].published_at.format("%B").to_string(),
+                    path: month_path(year, month),
+                    count: pages.len(),
+                    pages: pages
+                        .into_iter()
+                        .map(|page| Link {
+                            title: page.frontmatter.title.clone(),
+                            slug: page.frontmatter.slug.clone(),
+                            published_at: page.published_at,
+                        })
+                        .collect(),
+                })
+                .collect();
+
+            Year {
+                year,
+                count: months.iter().map(|month| month.count).sum(),
+                months,
+            }
+        })
+        .collect()
+}
+
+fn month_path(year: i32, month: u32) -> String {
+    format!("/archive/{year}/{month:02}")
+}
+
+fn render(
+    state: &SharedState,
+    years: Vec<Year>,
+    title: String,
+    path: String,
+) -> Result<Html<String>, AppError> {
+    let count: usize = years.iter().map(|year| year.count).sum();
+    let head = Head {
+        description: format!("{title}, {count} posts."),
+        title,
+        canonical_url: state.site.url(&path),
+    };
+
+    let rendered = state.templates.load().render_page(
+        "pages/archive",
+        head,
+        &ArchiveContext { years, count },
+    )?;
+
+    Ok(Html(rendered))
+}
+
+pub async fn index(State(state): State<Arc<SharedState>>) -> Result<Html<String>, AppError> {
+    let years = group(state.published_pages.snapshot().pages());
+
+    render(&state, years, "Archive".to_string(), "/archive".to_string())
+}
+
+pub async fn year(
+    Path(year): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Html<String>, AppError> {
+    let year: i32 = year.parse().map_err(|_| AppError::PageNotFound)?;
+
+    let years: Vec<Year> = group(state.published_pages.snapshot().pages())
+        .into_iter()
+        .filter(|period| period.year == year)
+        .collect();
+
+    if years.is_empty() {
+        return Err(AppError::PageNotFound);
+    }
+
+    render(
+        &state,
+        years,
+        format!("Posts from {year}"),
+        format!("/archive/{year}"),
+    )
+}
+
+pub async fn month(
+    Path((year, month)): Path<(String, String)>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Html<String>, AppError> {
+    let year: i32 = year.parse().map_err(|_| AppError::PageNotFound)?;
+    let month: u32 = month.parse().map_err(|_| AppError::PageNotFound)?;
+
+    let mut years: Vec<Year> = group(state.published_pages.snapshot().pages())
+        .into_iter()
+        .filter(|period| period.year == year)
+        .collect();
+
+    for period in &mut years {
+        period.months.retain(|period| period.month == month);
+        period.count = period.months.iter().map(|period| period.count).sum();
+    }
+    years.retain(|period| !period.months.is_empty());
+
+    let name = years
+        .first()
+        .and_then(|period| period.months.first())
+        .map(|period| period.name.clone())
+        .ok_or(AppError::PageNotFound)?;
+
+    render(
+        &state,
+        years,
+        format!("Posts from {name} {year}"),
+        month_path(year, month),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group, index, month, year};
+    use crate::{
+        SharedState,
+        pages::{Frontmatter, PublishedPage, test_support::state},
+    };
+    use axum::{
+        extract::{Path, State},
+        response::IntoResponse,
+    };
+    use chrono::{DateTime, Utc};
+    use std::sync::Arc;
+
+    fn create_page(slug: &str, published_at: &str) -> PublishedPage {
+        let published_at = DateTime::parse_from_rfc3339(published_at)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        PublishedPage {
+            path: slug.to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at,
+            frontmatter: Frontmatter {
+                published_at: Some(published_at),
+                slug: slug.to_string(),
+                title: format!("Title of {slug}"),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn create_pages() -> Vec<PublishedPage> {
+        vec![
+            create_page("newest", "2021-03-20T00:00:00Z"),
+            create_page("march", "2021-03-02T00:00:00Z"),
+            create_page("january", "2021-01-15T00:00:00Z"),
+            create_page("oldest", "2019-12-01T00:00:00Z"),
+        ]
+    }
+
+    fn create_shared_state() -> Arc<SharedState> {
+        state(create_pages(), Default::default())
+    }
+
+    async fn body_string(response: impl IntoResponse) -> String {
+        let body = axum::body::to_bytes(response.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_group_by_year_and_month() {
+        let pages: Vec<_> = create_pages().into_iter().map(Arc::new).collect();
+        let years = group(&pages);
+
+        assert_eq!(
+            years.iter().map(|year| year.year).collect::<Vec<_>>(),
+            vec![2021, 2019]
+        );
+        assert_eq!(years[0].count, 3);
+        assert_eq!(
+            years[0]
+                .months
+                .iter()
+                .map(|month| (month.name.as_str(), month.count))
+                .collect::<Vec<_>>(),
+            vec![("March", 2), ("January", 1)]
+        );
+        assert_eq!(years[0].months[0].pages[0].slug, "newest");
+        assert_eq!(years[1].months[0].name, "December");
+    }
+
+    #[tokio::test]
+    async fn test_index_lists_every_period() {
+        let body = body_string(index(State(create_shared_state())).await.unwrap()).await;
+
+        assert!(body.contains("href=\"/archive/2021\""));
+        assert!(body.contains("href=\"/archive/2021/03\""));
+        assert!(body.contains("href=\"/archive/2019/12\""));
+        assert!(body.contains("href=\"/post/oldest\""));
+    }
+
+    #[tokio::test]
+    async fn test_year_only_lists_that_year() {
+        let response = year(Path("2021".to_string()), State(create_shared_state()))
+            .await
+            .unwrap();
+        let body = body_string(response).await;
+
+        assert!(body.contains("Posts from 2021"));
+        assert!(body.contains("href=\"/post/january\""));
+        assert!(!body.contains("href=\"/post/oldest\""));
+    }
+
+    #[tokio::test]
+    async fn test_month_only_lists_that_month() {
+        let response = month(
+            Path(("2021".to_string(), "3".to_string())),
+            State(create_shared_state()),
+        )
+        .await
+        .unwrap();
+        let body = body_string(response).await;
+
+        assert!(body.contains("Posts from March 2021"));
+        assert!(body.contains("href=\"/post/march\""));
+        assert!(!body.contains("href=\"/post/january\""));
+    }
+
+    #[tokio::test]
+    async fn test_empty_or_invalid_periods_are_not_found() {
+        assert!(
+            year(Path("2020".to_string()), State(create_shared_state()))
+                .await
+                .is_err()
+        );
+        assert!(
+            year(Path("latest".to_string()), State(create_shared_state()))
+                .await
+                .is_err()
+        );
+        assert!(
+            month(
+                Path(("2021".to_string(), "02".to_string())),
+                State(create_shared_state())
+            )
+            .await
+            .is_err()
+        );
+        assert!(
+            month(
+                Path(("2021".to_string(), "13".to_string())),
+                State(create_shared_state())
+            )
+            .await
+            .is_err()
+        );
+    }
+}
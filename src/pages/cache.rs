@@ -0,0 +1,255 @@
+use crate::{
+    SharedState,
+    site::{CacheRoute, SiteConfig},
+};
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{
+            CACHE_CONTROL, CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        },
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::error;
+
+/// Marks a rendered page as cacheable so that [`conditional`] adds validators and the configured
+/// `Cache-Control`, and answers revalidation requests with a 304.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cached {
+    route: CacheRoute,
+    /// When the content last changed, sent as `Last-Modified`.
+    last_modified: Option<DateTime<Utc>>,
+}
+
+impl Cached {
+    pub fn new(route: CacheRoute, last_modified: Option<DateTime<Utc>>) -> Self {
+        Self {
+            route,
+            last_modified,
+        }
+    }
+}
+
+/// A strong validator over the rendered body, so that template changes invalidate caches even
+/// when no post changed.
+fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(&Sha256::digest(body)[..16]))
+}
+
+/// Formats a timestamp as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Whether the client's copy is still current. `If-Modified-Since` is only consulted when the
+/// request has no `If-None-Match`, as RFC 9110 requires.
+pub(crate) fn not_modified(
+    request: &HeaderMap,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    if let Some(if_none_match) = request.get(IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|tags| {
+            tags.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        });
+    }
+
+    let since = request
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok());
+
+    match (last_modified, since) {
+        // HTTP dates have whole seconds, so sub-second changes cannot be told apart.
+        (Some(last_modified), Some(since)) => last_modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+async fn revalidate(request: &HeaderMap, response: Response, site: &SiteConfig) -> Response {
+    let Some(cached) = response.extensions().get::<Cached>().copied() else {
+        return response;
+    };
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            error!("could not read response body: {}", err);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Something has gone wrong.",
+            )
+                .into_response();
+        }
+    };
+
+    let etag = etag(&body);
+    let headers = &mut parts.headers;
+    if let Ok(value) = HeaderValue::try_from(&etag) {
+        headers.insert(ETAG, value);
+    }
+    if let Some(last_modified) = cached.last_modified
+        && let Ok(value) = HeaderValue::try_from(http_date(last_modified))
+    {
+        headers.insert(LAST_MODIFIED, value);
+    }
+    // Policies are validated when the configuration loads.
+    if let Ok(value) = HeaderValue::from_str(site.cache_control().get(cached.route)) {
+        headers.insert(CACHE_CONTROL, value);
+    }
+
+    if not_modified(request, &etag, cached.last_modified) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Adds `ETag`, `Last-Modified` and `Cache-Control` to responses marked [`Cached`], and replaces
+/// them with a bodiless 304 when the request's validators show the client already has them.
+pub async fn conditional(
+    State(state): State<Arc<SharedState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut validators = HeaderMap::new();
+    for name in [IF_NONE_MATCH, IF_MODIFIED_SINCE] {
+        if let Some(value) = request.headers().get(&name) {
+            validators.insert(name, value.clone());
+        }
+    }
+
+    let response = next.run(request).await;
+
+    revalidate(&validators, response, &state.site).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cached, etag, http_date, not_modified, revalidate};
+    use crate::site::{CacheRoute, SiteConfig};
+    use axum::{
+        Extension,
+        http::{
+            HeaderMap, HeaderName, HeaderValue, StatusCode,
+            header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+        },
+        response::{Html, IntoResponse, Response},
+    };
+    use chrono::{DateTime, Duration, Utc};
+
+    fn published_at() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-03-01T12:30:00Z")
+            .unwrap()
+            .to_utc()
+    }
+
+    fn post_response() -> Response {
+        (
+            Extension(Cached::new(CacheRoute::Post, Some(published_at()))),
+            Html("<p>post</p>"),
+        )
+            .into_response()
+    }
+
+    fn validators(name: HeaderName, value: &str) -> HeaderMap {
+        HeaderMap::from_iter([(name, HeaderValue::from_str(value).unwrap())])
+    }
+
+    #[test]
+    fn test_http_date() {
+        assert_eq!(http_date(published_at()), "Fri, 01 Mar 2024 12:30:00 GMT");
+    }
+
+    #[test]
+    fn test_not_modified() {
+        let etag = etag(b"body");
+        let last_modified = Some(published_at());
+
+        let matching = validators(IF_NONE_MATCH, &format!("\"other\", W/{etag}"));
+        assert!(not_modified(&matching, &etag, last_modified));
+        assert!(!not_modified(
+            &validators(IF_NONE_MATCH, "\"other\""),
+            &etag,
+            last_modified
+        ));
+
+        let since = http_date(published_at());
+        assert!(not_modified(
+            &validators(IF_MODIFIED_SINCE, &since),
+            &etag,
+            last_modified
+        ));
+        let before = http_date(published_at() - Duration::hours(1));
+        assert!(!not_modified(
+            &validators(IF_MODIFIED_SINCE, &before),
+            &etag,
+            last_modified
+        ));
+
+        // A mismatched tag wins over a date that would otherwise match.
+        let mut both = validators(IF_NONE_MATCH, "\"other\"");
+        both.insert(IF_MODIFIED_SINCE, HeaderValue::from_str(&since).unwrap());
+        assert!(!not_modified(&both, &etag, last_modified));
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_adds_validators_and_answers_304() {
+        let site = SiteConfig::default();
+
+        let response = revalidate(&HeaderMap::new(), post_response(), &site).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=300");
+        assert_eq!(
+            response.headers()[LAST_MODIFIED],
+            "Fri, 01 Mar 2024 12:30:00 GMT"
+        );
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+
+        let response = revalidate(&validators(IF_NONE_MATCH, &etag), post_response(), &site).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag.as_str());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        let later = http_date(Utc::now());
+        let response = revalidate(
+            &validators(IF_MODIFIED_SINCE, &later),
+            post_response(),
+            &site,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_leaves_unmarked_responses_alone() {
+        let response = revalidate(
+            &HeaderMap::new(),
+            Html("<p>search</p>").into_response(),
+            &SiteConfig::default(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(ETAG).is_none());
+        assert!(response.headers().get(CACHE_CONTROL).is_none());
+    }
+}
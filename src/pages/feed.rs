@@ -0,0 +1,454 @@
+use super::PublishedPage;
+use crate::{AppError, SharedState, site::SiteConfig};
+use anyhow::{Context, anyhow};
+use axum::{
+    Json,
+    body::Body,
+    extract::State,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use xml_builder::{XMLBuilder, XMLElement, XMLVersion};
+
+fn page_url(site: &SiteConfig, page: &PublishedPage) -> String {
+    site.url(&format!("/post/{}", page.frontmatter.slug))
+}
+
+/// Post bodies link within the site by root-relative paths such as `/post/...` and `/images/...`,
+/// which feed readers cannot resolve, so they are made absolute against the base URL.
+/// Protocol-relative `//host` links are left alone.
+fn absolute_links(site: &SiteConfig, html: &str) -> String {
+    const ATTRIBUTES: [&str; 2] = ["href=\"/", "src=\"/"];
+
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some((index, attribute)) = ATTRIBUTES
+        .iter()
+        .filter_map(|attribute| rest.find(attribute).map(|index| (index, attribute)))
+        .min()
+    {
+        // Everything up to the opening quote, leaving the path's leading `/` in `rest`.
+        let path = index + attribute.len() - 1;
+        output.push_str(&rest[..path]);
+        rest = &rest[path..];
+
+        if !rest.starts_with("//") {
+            output.push_str(site.base_url());
+        }
+        output.push('/');
+        rest = &rest[1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// The most recent change across all pages, or now when there are none.
+fn feed_updated_at(pages: &[Arc<PublishedPage>]) -> DateTime<Utc> {
+    pages
+        .iter()
+        .map(|page| page.updated_at())
+        .max()
+        .unwrap_or(Utc::now())
+}
+
+fn text_element(name: &str, text: impl Into<String>) -> Result<XMLElement, AppError> {
+    let mut element = XMLElement::new(name);
+    element
+        .add_text(text.into())
+        .map_err(|e| anyhow!("Failed to add text to {name}: {}", e))?;
+    Ok(element)
+}
+
+fn add_child(parent: &mut XMLElement, child: XMLElement) -> Result<(), AppError> {
+    parent
+        .add_child(child)
+        .map_err(|e| anyhow!("Failed to add child element: {}", e))?;
+    Ok(())
+}
+
+fn xml_response(root: XMLElement, content_type: &'static str) -> Result<Body, AppError> {
+    let mut xml = XMLBuilder::new()
+        .version(XMLVersion::XML1_0)
+        .encoding("UTF-8".into())
+        .build();
+    xml.set_root_element(root);
+
+    let mut output = Vec::<u8>::new();
+    xml.generate(&mut output)
+        .map_err(|e| anyhow!("Failed to generate {content_type}: {}", e))?;
+
+    let xml_string = String::from_utf8(output).context("could not render XML")?;
+
+    Ok(Body::from(xml_string))
+}
+
+/// RSS 2.0 at `/feed.xml`. Items carry the preview as `description` and the full post as
+/// `content:encoded`.
+pub async fn rss(State(state): State<Arc<SharedState>>) -> Result<impl IntoResponse, AppError> {
+    let publication = state.published_pages.snapshot();
+    let published_pages = publication.pages();
+
+    let mut rss = XMLElement::new("rss");
+    rss.add_attribute("version", "2.0");
+    rss.add_attribute("xmlns:atom", "http://www.w3.org/2005/Atom");
+    rss.add_attribute("xmlns:content", "http://purl.org/rss/1.0/modules/content/");
+
+    let mut channel = XMLElement::new("channel");
+    add_child(&mut channel, text_element("title", state.site.title())?)?;
+    add_child(&mut channel, text_element("link", state.site.base_url())?)?;
+    add_child(
+        &mut channel,
+        text_element("description", state.site.description())?,
+    )?;
+    add_child(&mut channel, text_element("language", "en")?)?;
+    add_child(
+        &mut channel,
+        text_element(
+            "lastBuildDate",
+            feed_updated_at(published_pages).to_rfc2822(),
+        )?,
+    )?;
+
+    let mut self_link = XMLElement::new("atom:link");
+    self_link.add_attribute("href", &state.site.url("/feed.xml"));
+    self_link.add_attribute("rel", "self");
+    self_link.add_attribute("type", "application/rss+xml");
+    add_child(&mut channel, self_link)?;
+
+    for page in published_pages {
+        let url = page_url(&state.site, page);
+        let mut item = XMLElement::new("item");
+
+        add_child(&mut item, text_element("title", &page.frontmatter.title)?)?;
+        add_child(&mut item, text_element("link", &url)?)?;
+
+        let mut guid = text_element("guid", &url)?;
+        guid.add_attribute("isPermaLink", "true");
+        add_child(&mut item, guid)?;
+
+        add_child(
+            &mut item,
+            text_element("pubDate", page.published_at.to_rfc2822())?,
+        )?;
+        add_child(
+            &mut item,
+            text_element("description", &page.frontmatter.preview)?,
+        )?;
+        add_child(
+            &mut item,
+            text_element(
+                "content:encoded",
+                absolute_links(&state.site, &page.content),
+            )?,
+        )?;
+
+        for tag in &page.frontmatter.tags {
+            add_child(&mut item, text_element("category", tag)?)?;
+        }
+
+        add_child(&mut channel, item)?;
+    }
+
+    add_child(&mut rss, channel)?;
+
+    Ok((
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/rss+xml"),
+        )],
+        xml_response(rss, "RSS")?,
+    ))
+}
+
+/// Atom at `/atom.xml`, with `revised_at` as each entry's `updated` timestamp.
+pub async fn atom(State(state): State<Arc<SharedState>>) -> Result<impl IntoResponse, AppError> {
+    let publication = state.published_pages.snapshot();
+    let published_pages = publication.pages();
+
+    let mut feed = XMLElement::new("feed");
+    feed.add_attribute("xmlns", "http://www.w3.org/2005/Atom");
+    feed.add_attribute("xml:base", &state.site.url("/"));
+
+    add_child(&mut feed, text_element("id", state.site.url("/"))?)?;
+    add_child(&mut feed, text_element("title", state.site.title())?)?;
+    add_child(
+        &mut feed,
+        text_element("subtitle", state.site.description())?,
+    )?;
+    add_child(
+        &mut feed,
+        text_element("updated", feed_updated_at(published_pages).to_rfc3339())?,
+    )?;
+
+    let mut self_link = XMLElement::new("link");
+    self_link.add_attribute("href", &state.site.url("/atom.xml"));
+    self_link.add_attribute("rel", "self");
+    self_link.add_attribute("type", "application/atom+xml");
+    add_child(&mut feed, self_link)?;
+
+    let mut alternate_link = XMLElement::new("link");
+    alternate_link.add_attribute("href", state.site.base_url());
+    alternate_link.add_attribute("rel", "alternate");
+    alternate_link.add_attribute("type", "text/html");
+    add_child(&mut feed, alternate_link)?;
+
+    let mut author = XMLElement::new("author");
+    add_child(&mut author, text_element("name", state.site.author())?)?;
+    add_child(&mut feed, author)?;
+
+    for page in published_pages {
+        let url = page_url(&state.site, page);
+        let mut entry = XMLElement::new("entry");
+        entry.add_attribute("xml:base", &url);
+
+        add_child(&mut entry, text_element("id", &url)?)?;
+        add_child(&mut entry, text_element("title", &page.frontmatter.title)?)?;
+
+        let mut link = XMLElement::new("link");
+        link.add_attribute("href", &url);
+        link.add_attribute("rel", "alternate");
+        link.add_attribute("type", "text/html");
+        add_child(&mut entry, link)?;
+
+        add_child(
+            &mut entry,
+            text_element("published", page.published_at.to_rfc3339())?,
+        )?;
+        add_child(
+            &mut entry,
+            text_element("updated", page.updated_at().to_rfc3339())?,
+        )?;
+        add_child(
+            &mut entry,
+            text_element("summary", &page.frontmatter.preview)?,
+        )?;
+
+        let mut content = text_element("content", absolute_links(&state.site, &page.content))?;
+        content.add_attribute("type", "html");
+        add_child(&mut entry, content)?;
+
+        for tag in &page.frontmatter.tags {
+            let mut category = XMLElement::new("category");
+            category.add_attribute("term", tag);
+            add_child(&mut entry, category)?;
+        }
+
+        add_child(&mut feed, entry)?;
+    }
+
+    Ok((
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/atom+xml"),
+        )],
+        xml_response(feed, "Atom")?,
+    ))
+}
+
+#[derive(Serialize, Debug)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    home_page_url: String,
+    feed_url: String,
+    description: &'a str,
+    language: &'static str,
+    authors: Vec<JsonFeedAuthor<'a>>,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize, Debug)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: String,
+    content_html: String,
+    summary: String,
+    date_published: String,
+    date_modified: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+/// JSON Feed 1.1 at `/feed.json`.
+pub async fn json(State(state): State<Arc<SharedState>>) -> Result<Response, AppError> {
+    let publication = state.published_pages.snapshot();
+    let published_pages = publication.pages();
+
+    let items = published_pages
+        .iter()
+        .map(|page| JsonFeedItem {
+            id: page_url(&state.site, page),
+            url: page_url(&state.site, page),
+            title: page.frontmatter.title.clone(),
+            content_html: absolute_links(&state.site, &page.content),
+            summary: page.frontmatter.preview.clone(),
+            date_published: page.published_at.to_rfc3339(),
+            date_modified: page.updated_at().to_rfc3339(),
+            tags: page.frontmatter.tags.clone(),
+        })
+        .collect();
+
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: state.site.title(),
+        home_page_url: state.site.base_url().to_string(),
+        feed_url: state.site.url("/feed.json"),
+        description: state.site.description(),
+        language: "en",
+        authors: vec![JsonFeedAuthor {
+            name: state.site.author(),
+        }],
+        items,
+    };
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/feed+json"),
+        )],
+        Json(feed),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{absolute_links, atom, json, rss};
+    use crate::{
+        SharedState,
+        pages::{Frontmatter, PublishedPage, test_support::state},
+        site::SiteConfig,
+    };
+    use axum::{extract::State, response::IntoResponse};
+    use chrono::{DateTime, Utc};
+    use std::sync::Arc;
+
+    fn create_shared_state() -> Arc<SharedState> {
+        let pages = vec![
+            create_page("revised", "Revised & updated", Some("2023-01-15T00:00:00Z")),
+            create_page("original", "Original", None),
+        ];
+
+        state(pages, SiteConfig::new("https://corybuecker.com").unwrap())
+    }
+
+    fn timestamp(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn create_page(slug: &str, title: &str, revised_at: Option<&str>) -> PublishedPage {
+        let published_at = timestamp("2023-01-01T00:00:00Z");
+
+        PublishedPage {
+            path: slug.to_string(),
+            content: "<p>this is the <em>page</em> content, <a href=\"/post/x\">linked</a></p>"
+                .to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at,
+            frontmatter: Frontmatter {
+                description: format!("{title} description"),
+                preview: format!("{title} preview"),
+                published_at: Some(published_at),
+                revised_at: revised_at.map(timestamp),
+                slug: slug.to_string(),
+                tags: vec!["Kubernetes".to_string()],
+                title: title.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    async fn body_and_content_type(response: impl IntoResponse) -> (String, String) {
+        let response = response.into_response();
+        let content_type = response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (String::from_utf8(body.to_vec()).unwrap(), content_type)
+    }
+
+    #[test]
+    fn test_absolute_links() {
+        let site = SiteConfig::new("https://corybuecker.com").unwrap();
+        let html = concat!(
+            r#"<a href="/post/x">x</a> <img src="/images/cover.png" /> "#,
+            r#"<a href="https://example.com/">out</a> <img src="//cdn.example.com/a.png" /> "#,
+            r##"<a href="#heading">anchor</a>"##,
+        );
+
+        assert_eq!(
+            absolute_links(&site, html),
+            concat!(
+                r#"<a href="https://corybuecker.com/post/x">x</a> "#,
+                r#"<img src="https://corybuecker.com/images/cover.png" /> "#,
+                r#"<a href="https://example.com/">out</a> <img src="//cdn.example.com/a.png" /> "#,
+                r##"<a href="#heading">anchor</a>"##,
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rss_includes_escaped_full_content() {
+        let response = rss(State(create_shared_state())).await.unwrap();
+        let (body, content_type) = body_and_content_type(response).await;
+
+        assert_eq!(content_type, "application/rss+xml");
+        assert!(body.contains("<rss version=\"2.0\""));
+        assert!(body.contains("<link>https://corybuecker.com/post/original</link>"));
+        assert!(body.contains("Revised &amp; updated"));
+        assert!(body.contains("&lt;em&gt;page&lt;/em&gt;"));
+        assert!(body.contains("&lt;a href=&quot;https://corybuecker.com/post/x&quot;&gt;"));
+        assert!(body.contains("<category>Kubernetes</category>"));
+        assert!(body.contains("<lastBuildDate>Sun, 15 Jan 2023 00:00:00 +0000</lastBuildDate>"));
+    }
+
+    #[tokio::test]
+    async fn test_atom_uses_revised_at_for_updated() {
+        let response = atom(State(create_shared_state())).await.unwrap();
+        let (body, content_type) = body_and_content_type(response).await;
+
+        assert_eq!(content_type, "application/atom+xml");
+        assert!(body.contains("<id>https://corybuecker.com/post/revised</id>"));
+        assert!(body.contains("<updated>2023-01-15T00:00:00+00:00</updated>"));
+        assert!(body.contains("<published>2023-01-01T00:00:00+00:00</published>"));
+        assert!(body.contains("<content type=\"html\">"));
+        assert!(body.contains("xml:base=\"https://corybuecker.com/\""));
+        assert!(body.contains("<entry xml:base=\"https://corybuecker.com/post/revised\">"));
+        assert!(body.contains("href=&quot;https://corybuecker.com/post/x&quot;"));
+    }
+
+    #[tokio::test]
+    async fn test_json_feed_items() {
+        let response = json(State(create_shared_state())).await.unwrap();
+        let (body, content_type) = body_and_content_type(response).await;
+
+        assert_eq!(content_type, "application/feed+json");
+
+        assert!(body.contains(r#""version":"https://jsonfeed.org/version/1.1""#));
+        assert!(body.contains(r#""url":"https://corybuecker.com/post/revised""#));
+        assert!(body.contains(r#""date_modified":"2023-01-15T00:00:00+00:00""#));
+        assert!(body.contains(r#""date_modified":"2023-01-01T00:00:00+00:00""#));
+        assert!(body.contains(
+            r#""content_html":"<p>this is the <em>page</em> content, <a href=\"https://corybuecker.com/post/x\">linked</a></p>""#
+        ));
+    }
+}
@@ -1,106 +1,230 @@
-use super::without_frontmatter;
-use crate::{AppError, SharedState};
+use super::{ReadingTime, cache::Cached};
+use crate::{
+    AppError, SharedState,
+    site::{CacheRoute, HomeMode},
+    templates::Head,
+};
 use anyhow::anyhow;
-use axum::{extract::State, response::Html};
-use rust_web_common::templating::to_json;
+use axum::{
+    Extension,
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use chrono::{DateTime, Utc};
 use serde::Serialize;
-use std::{collections::VecDeque, sync::Arc};
+use std::sync::Arc;
 
 #[derive(Serialize, Debug)]
 struct Link {
     title: String,
     slug: String,
+    reading_time: ReadingTime,
+}
+
+#[derive(Serialize, Debug)]
+struct Card {
+    title: String,
+    slug: String,
+    preview: String,
+    published_at: DateTime<Utc>,
+    reading_time: ReadingTime,
+}
+
+#[derive(Serialize, Debug)]
+struct Pagination {
+    current: usize,
+    total: usize,
+    previous: Option<String>,
+    next: Option<String>,
+}
+
+/// The context of `pages/home`: the newest post in full, then links to the older ones.
+#[derive(Serialize, Debug)]
+struct HomeContext<'a> {
+    content: &'a str,
+    published_at: DateTime<Utc>,
+    revised_at: Option<DateTime<Utc>>,
+    reading_time: ReadingTime,
+    pages: Vec<Link>,
+}
+
+/// The context of `pages/cards`.
+#[derive(Serialize, Debug)]
+struct CardsContext {
+    cards: Vec<Card>,
+    pagination: Pagination,
+}
+
+/// The path of a page of cards. The first page lives at the homepage itself.
+fn page_path(number: usize) -> String {
+    match number {
+        1 => "/".to_string(),
+        number => format!("/page/{number}"),
+    }
 }
 
 pub async fn build_response(
     State(shared_state): State<Arc<SharedState>>,
-) -> Result<Html<String>, AppError> {
-    let renderer = &shared_state.renderer;
-    let published_pages = shared_state.published_pages.get_all()?;
+) -> Result<Response, AppError> {
+    match shared_state.site.home_mode() {
+        HomeMode::Latest => latest(&shared_state),
+        HomeMode::Cards => cards(&shared_state, 1),
+    }
+}
+
+/// Later pages of cards at `/page/{n}`. Only served when the homepage is in cards mode.
+pub async fn page(
+    Path(number): Path<String>,
+    State(shared_state): State<Arc<SharedState>>,
+) -> Result<Response, AppError> {
+    if shared_state.site.home_mode() != HomeMode::Cards {
+        return Err(AppError::PageNotFound);
+    }
+
+    let number: usize = number.parse().map_err(|_| AppError::PageNotFound)?;
+
+    match number {
+        0 => Err(AppError::PageNotFound),
+        1 => Ok(Redirect::permanent("/").into_response()),
+        number => cards(&shared_state, number),
+    }
+}
+
+fn latest(shared_state: &SharedState) -> Result<Response, AppError> {
+    let publication = shared_state.published_pages.snapshot();
+    let published_pages = publication.pages();
     let published_page = published_pages
         .first()
         .ok_or(anyhow!("could not get homepage"))?;
 
-    let content = shared_state
-        .published_pages
-        .read(&published_page.path.to_string())
-        .await?;
-    let content = without_frontmatter(&content).await?;
-
     let description = published_page.frontmatter.description.clone();
     let published_at = published_page.published_at;
     let title = published_page.frontmatter.title.clone();
     let revised_at = published_page.frontmatter.revised_at;
+    let reading_time = published_page.reading_time;
 
-    let mut pages: VecDeque<Link> = published_pages
-        .into_iter()
+    let pages: Vec<Link> = published_pages
+        .iter()
+        .skip(1)
         .map(|row| Link {
-            title: row.frontmatter.title,
-            slug: row.frontmatter.slug,
+            title: row.frontmatter.title.clone(),
+            slug: row.frontmatter.slug.clone(),
+            reading_time: row.reading_time,
         })
         .collect();
 
-    pages.pop_front();
+    let head = Head {
+        title,
+        description,
+        canonical_url: shared_state.site.url("/"),
+    };
+    let context = HomeContext {
+        content: &published_page.content,
+        published_at,
+        revised_at,
+        reading_time,
+        pages,
+    };
 
-    renderer.insert("pages", to_json(pages))?;
-    renderer.insert("content", content)?;
-    renderer.insert("description", description)?;
-    renderer.insert("title", title)?;
-    renderer.insert("published_at", to_json(published_at))?;
-    renderer.insert("revised_at", to_json(revised_at))?;
-    renderer.insert("partial", "pages/home")?;
+    let templates = shared_state.templates.load();
+    let rendered = templates.render_page("pages/home", head, &context)?;
 
-    let rendered = renderer
-        .render("layout")
-        .map_err(|e| anyhow!("could not render template: {e}"))?;
+    let last_modified = publication.updated_at().max(Some(templates.loaded_at()));
+    let cached = Cached::new(CacheRoute::Home, last_modified);
 
-    Ok(Html(rendered))
+    Ok((Extension(cached), Html(rendered)).into_response())
+}
+
+fn cards(shared_state: &SharedState, number: usize) -> Result<Response, AppError> {
+    let publication = shared_state.published_pages.snapshot();
+    let published_pages = publication.pages();
+    let posts_per_page = shared_state.site.posts_per_page();
+    let total = published_pages.len().div_ceil(posts_per_page).max(1);
+
+    if number > total {
+        return Err(AppError::PageNotFound);
+    }
+
+    let cards: Vec<Card> = published_pages
+        .iter()
+        .skip((number - 1) * posts_per_page)
+        .take(posts_per_page)
+        .map(|page| Card {
+            title: page.frontmatter.title.clone(),
+            slug: page.frontmatter.slug.clone(),
+            preview: page.frontmatter.preview.clone(),
+            published_at: page.published_at,
+            reading_time: page.reading_time,
+        })
+        .collect();
+
+    let pagination = Pagination {
+        current: number,
+        total,
+        previous: (number > 1).then(|| page_path(number - 1)),
+        next: (number < total).then(|| page_path(number + 1)),
+    };
+
+    let title = match number {
+        1 => "Latest posts".to_string(),
+        number => format!("Posts, page {number} of {total}"),
+    };
+
+    let head = Head {
+        title,
+        description: shared_state.site.description().to_string(),
+        canonical_url: shared_state.site.url(&page_path(number)),
+    };
+
+    let templates = shared_state.templates.load();
+    let rendered =
+        templates.render_page("pages/cards", head, &CardsContext { cards, pagination })?;
+
+    let last_modified = publication.updated_at().max(Some(templates.loaded_at()));
+    let cached = Cached::new(CacheRoute::Home, last_modified);
+
+    Ok((Extension(cached), Html(rendered)).into_response())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::build_response;
+    use super::{build_response, page};
     use crate::{
         SharedState,
-        pages::{Frontmatter, PublicationManager, PublishedPage},
+        pages::{Frontmatter, PublishedPage, test_support::state},
+        site::SiteConfig,
+    };
+    use axum::{
+        extract::{Path, State},
+        http::StatusCode,
+        response::IntoResponse,
     };
-    use anyhow::Result;
-    use axum::{extract::State, response::IntoResponse};
     use chrono::{DateTime, Utc};
-    use rust_web_common::templating::Renderer;
-    use std::{future::Future, pin::Pin, sync::Arc};
+    use std::sync::Arc;
 
-    struct MockPublishedPages {
-        pages: Vec<PublishedPage>,
+    fn cards_site() -> SiteConfig {
+        toml::from_str("home_mode = \"cards\"\nposts_per_page = 2").unwrap()
     }
 
-    impl PublicationManager for MockPublishedPages {
-        fn get_all(&self) -> Result<Vec<PublishedPage>> {
-            Ok(self.pages.clone())
-        }
-
-        fn publish<'f>(
-            &'f mut self,
-        ) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>> {
-            Box::pin(async move { Ok(self.pages.len()) })
-        }
-
-        fn read<'f>(
-            &'f self,
-            _path: &'f str,
-        ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + Sync + 'f>> {
-            Box::pin(async move { Ok("this is the page content".to_string()) })
-        }
+    fn create_pages(count: usize) -> Vec<PublishedPage> {
+        (1..=count)
+            .map(|index| {
+                create_page(
+                    &format!("test-page{index}"),
+                    &format!("page{index}"),
+                    &format!("Page {index}"),
+                    &format!("Page {index} description"),
+                    None,
+                )
+            })
+            .collect()
     }
 
-    async fn create_shared_state(pages: Vec<PublishedPage>) -> Arc<SharedState> {
-        let mock_pages = MockPublishedPages { pages };
-        let renderer = Renderer::new("templates".to_string()).unwrap();
-
-        Arc::new(SharedState {
-            renderer,
-            published_pages: Box::new(mock_pages),
-        })
+    async fn body_string(response: impl IntoResponse) -> String {
+        let body = axum::body::to_bytes(response.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
     }
 
     fn create_page(
@@ -112,6 +236,9 @@ mod tests {
     ) -> PublishedPage {
         PublishedPage {
             path: path.to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
             published_at: Utc::now(),
             frontmatter: Frontmatter {
                 description: description.to_string(),
@@ -120,6 +247,7 @@ mod tests {
                 revised_at,
                 slug: slug.to_string(),
                 title: title.to_string(),
+                ..Default::default()
             },
         }
     }
@@ -140,7 +268,7 @@ mod tests {
             create_page("test-page2", "page2", "Page 2", "Page 2 description", None),
         ];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let body_string = execute_request_and_get_body(state).await;
 
         // Check that the home page title is rendered with the suffix
@@ -165,7 +293,7 @@ mod tests {
             Some(revised_date),
         )];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let body_string = execute_request_and_get_body(state).await;
 
         assert!(body_string.contains("Revised Page"));
@@ -173,7 +301,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_build_response_no_pages_error() {
-        let state = create_shared_state(vec![]).await;
+        let state = state(vec![], Default::default());
         let result = build_response(State(state)).await;
         assert!(result.is_err());
     }
@@ -188,10 +316,71 @@ mod tests {
             None,
         )];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let body_string = execute_request_and_get_body(state).await;
 
         // With only one page, the pages list should be empty after pop_front()
         assert!(body_string.contains("Single Page"));
     }
+
+    #[tokio::test]
+    async fn test_cards_mode_renders_previews_for_first_page() {
+        let state = state(create_pages(3), cards_site());
+        let body = body_string(build_response(State(state)).await.unwrap()).await;
+
+        assert!(body.contains("Page 1 preview"));
+        assert!(body.contains("Page 2 preview"));
+        assert!(!body.contains("Page 3 preview"));
+        assert!(!body.contains("this is the page content"));
+        assert!(body.contains("href=\"/page/2\""));
+        assert!(body.contains("min read"));
+    }
+
+    #[tokio::test]
+    async fn test_both_modes_share_the_canonical_url() {
+        let canonical = "<link rel=\"canonical\" href=\"https://corybuecker.com/\" />";
+
+        let latest = state(create_pages(3), Default::default());
+        let body = body_string(build_response(State(latest)).await.unwrap()).await;
+        assert!(body.contains(canonical));
+
+        let cards = state(create_pages(3), cards_site());
+        let body = body_string(build_response(State(cards)).await.unwrap()).await;
+        assert!(body.contains(canonical));
+    }
+
+    #[tokio::test]
+    async fn test_cards_mode_later_page() {
+        let state = state(create_pages(3), cards_site());
+        let response = page(Path("2".to_string()), State(state)).await.unwrap();
+        let body = body_string(response).await;
+
+        assert!(body.contains("Page 3 preview"));
+        assert!(!body.contains("Page 1 preview"));
+        assert!(body.contains("rel=\"prev\" href=\"/\""));
+        assert!(!body.contains("rel=\"next\""));
+    }
+
+    #[tokio::test]
+    async fn test_cards_mode_page_bounds() {
+        let state = state(create_pages(3), cards_site());
+
+        let first = page(Path("1".to_string()), State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::PERMANENT_REDIRECT);
+
+        for number in ["0", "3", "two"] {
+            let result = page(Path(number.to_string()), State(state.clone())).await;
+            assert!(result.is_err(), "page {number} should not exist");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latest_mode_has_no_pagination() {
+        let state = state(create_pages(3), Default::default());
+
+        let result = page(Path("2".to_string()), State(state)).await;
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,258 @@
+use super::{PublishedPage, redirects};
+use crate::{AppError, SharedState, site::Identity};
+use anyhow::Result;
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use serde::Serialize;
+use std::{cmp::Reverse, collections::HashSet, sync::Arc};
+use tracing::error;
+
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Marks a response as a miss so that [`render`] can replace it with the 404 page.
+#[derive(Clone, Copy, Debug)]
+pub struct NotFound;
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Suggestion {
+    title: String,
+    slug: String,
+}
+
+/// The context of `errors/404`, which is a page of its own rather than a partial of the layout.
+#[derive(Serialize, Debug)]
+struct NotFoundContext<'a> {
+    site: &'a Identity,
+    suggestions: Vec<Suggestion>,
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Words of a slug long enough to be meaningful, so `a` or `to` alone never make a match.
+fn tokens(slug: &str) -> impl Iterator<Item = &str> {
+    slug.split('-').filter(|token| token.len() >= 3)
+}
+
+/// Published posts whose slug looks like the one requested, closest first. A post qualifies when
+/// its slug is within a third of its length in edits, e.g. a typo, or shares a word with it, e.g.
+/// a truncated link.
+fn suggest(pages: &[Arc<PublishedPage>], slug: &str) -> Vec<Suggestion> {
+    let wanted: HashSet<&str> = tokens(slug).collect();
+
+    let mut candidates: Vec<(usize, usize, &PublishedPage)> = pages
+        .iter()
+        .filter_map(|page| {
+            let candidate = page.frontmatter.slug.as_str();
+            let distance = edit_distance(slug, candidate);
+            let shared = tokens(candidate)
+                .filter(|token| wanted.contains(token))
+                .count();
+            let length = slug.chars().count().max(candidate.chars().count());
+
+            (distance * 3 <= length || shared > 0).then_some((distance, shared, page.as_ref()))
+        })
+        .collect();
+
+    candidates.sort_by_key(|(distance, shared, _)| (*distance, Reverse(*shared)));
+
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, page)| Suggestion {
+            title: page.frontmatter.title.clone(),
+            slug: page.frontmatter.slug.clone(),
+        })
+        .collect()
+}
+
+fn render_page(state: &SharedState, path: &str) -> Result<String> {
+    let suggestions = match path.strip_prefix("/post/") {
+        Some(slug) => suggest(
+            state.published_pages.snapshot().pages(),
+            slug.trim_end_matches('/'),
+        ),
+        None => Vec::new(),
+    };
+
+    let templates = state.templates.load();
+
+    templates.render(
+        "errors/404",
+        &NotFoundContext {
+            site: templates.site(),
+            suggestions,
+        },
+    )
+}
+
+/// What a miss at `path` turns into: a 301 when the redirect table says it has moved, and the 404
+/// page otherwise.
+fn miss(state: &SharedState, path: &str) -> Response {
+    match redirects::find(state, path) {
+        Ok(Some(redirect)) => return redirect,
+        Ok(None) => {}
+        Err(err) => return err.into_response(),
+    }
+
+    match render_page(state, path) {
+        Ok(page) => (StatusCode::NOT_FOUND, Html(page)).into_response(),
+        Err(err) => {
+            error!("could not render 404 page: {}", err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Something has gone wrong.",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Handles every request that matched no route, leaving the response to [`render`].
+pub async fn fallback() -> AppError {
+    AppError::PageNotFound
+}
+
+/// Replaces any response marked [`NotFound`] with a redirect, for paths that have moved, or with
+/// the 404 page, with suggestions for mistyped post URLs. Every miss goes through here, so moved
+/// paths redirect even under routed prefixes such as `/tags/{tag}`.
+pub async fn render(
+    State(state): State<Arc<SharedState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+
+    if response.extensions().get::<NotFound>().is_none() {
+        return response;
+    }
+
+    miss(&state, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NotFound, edit_distance, miss, render_page, suggest};
+    use crate::{
+        SharedState,
+        pages::{
+            Frontmatter, PublishedPage, tags,
+            test_support::{MockPublishedPages, shared_state, state},
+        },
+    };
+    use axum::{
+        extract::{Path, State},
+        http::StatusCode,
+        response::IntoResponse,
+    };
+    use chrono::Utc;
+    use std::{collections::BTreeMap, sync::Arc};
+
+    fn create_page(slug: &str) -> PublishedPage {
+        PublishedPage {
+            path: slug.to_string(),
+            content: String::new(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                slug: slug.to_string(),
+                title: format!("Title of {slug}"),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn create_pages() -> Vec<PublishedPage> {
+        vec![
+            create_page("an-introduction"),
+            create_page("automating-cloud-run-deploy"),
+            create_page("kubernetes-mail-server"),
+            create_page("rust-web-services"),
+        ]
+    }
+
+    fn create_shared_state() -> Arc<SharedState> {
+        state(create_pages(), Default::default())
+    }
+
+    fn slugs(pages: &[Arc<PublishedPage>], slug: &str) -> Vec<String> {
+        suggest(pages, slug)
+            .into_iter()
+            .map(|suggestion| suggestion.slug)
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_suggest_typos_and_shared_words() {
+        let pages: Vec<_> = create_pages().into_iter().map(Arc::new).collect();
+
+        assert_eq!(slugs(&pages, "an-introdution"), vec!["an-introduction"]);
+        assert_eq!(slugs(&pages, "mail-server"), vec!["kubernetes-mail-server"]);
+        assert!(slugs(&pages, "something-else-entirely").is_empty());
+    }
+
+    #[test]
+    fn test_render_page_lists_suggestions_for_posts_only() {
+        let state = create_shared_state();
+
+        let page = render_page(&state, "/post/rust-web-service/").unwrap();
+        assert!(page.contains("Page Not Found - Cory Buecker"));
+        assert!(page.contains("href=\"/post/rust-web-services\""));
+
+        let page = render_page(&state, "/rust-web-service").unwrap();
+        assert!(!page.contains("href=\"/post/"));
+    }
+
+    #[tokio::test]
+    async fn test_miss_redirects_moved_paths_under_routes() {
+        let published_pages = MockPublishedPages {
+            pages: create_pages(),
+            redirects: BTreeMap::from([(
+                "/tags/old-name".to_string(),
+                "/tags/new-name".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let state = Arc::new(shared_state(published_pages, Default::default()));
+
+        // The tag route answers first, and only its miss reaches the redirect table.
+        let response = tags::show(Path("old-name".to_string()), State(state.clone()))
+            .await
+            .into_response();
+        assert!(response.extensions().get::<NotFound>().is_some());
+
+        let response = miss(&state, "/tags/old-name/");
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()["location"], "/tags/new-name");
+
+        let response = miss(&state, "/tags/missing");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,270 @@
+use super::PublishedPage;
+use crate::{AppError, SharedState};
+use anyhow::anyhow;
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderValue, header},
+    response::IntoResponse,
+};
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{self, fontdb},
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+use tokio::task::spawn_blocking;
+use tracing::{instrument, warn};
+
+pub const WIDTH: u32 = 1200;
+pub const HEIGHT: u32 = 630;
+
+/// Roughly how many characters of the title fit on one line of the card at its font size.
+const LINE_LENGTH: usize = 30;
+const MAX_LINES: usize = 4;
+
+/// Fonts are looked up once from the system, e.g. `fonts-dejavu-core` in the container image.
+static FONTS: LazyLock<Arc<fontdb::Database>> = LazyLock::new(|| {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    if fonts.is_empty() {
+        warn!("no system fonts found, social cards will render without text");
+    }
+    Arc::new(fonts)
+});
+
+/// Generated cards by slug. The fingerprint covers everything drawn on the card, so a changed
+/// title or date renders a fresh card instead of serving the cached one.
+#[derive(Default)]
+pub struct CardCache {
+    cards: Mutex<HashMap<String, (String, Bytes)>>,
+}
+
+impl CardCache {
+    fn get(&self, slug: &str, fingerprint: &str) -> Option<Bytes> {
+        let cards = self.cards.lock().ok()?;
+        cards
+            .get(slug)
+            .filter(|(cached, _)| cached == fingerprint)
+            .map(|(_, png)| png.clone())
+    }
+
+    fn insert(&self, slug: &str, fingerprint: String, png: Bytes) {
+        if let Ok(mut cards) = self.cards.lock() {
+            cards.insert(slug.to_string(), (fingerprint, png));
+        }
+    }
+
+    /// Drops every card, e.g. after the card template changes.
+    pub fn clear(&self) {
+        if let Ok(mut cards) = self.cards.lock() {
+            cards.clear();
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Card {
+    width: u32,
+    height: u32,
+    lines: Vec<String>,
+    date: String,
+    site_name: String,
+}
+
+impl Card {
+    fn new(page: &PublishedPage, site_name: &str) -> Self {
+        Card {
+            width: WIDTH,
+            height: HEIGHT,
+            lines: wrap_title(&page.frontmatter.title),
+            date: page.published_at.format("%B %-d, %Y").to_string(),
+            site_name: site_name.to_string(),
+        }
+    }
+
+    fn fingerprint(&self) -> String {
+        format!("{}\n{}", self.lines.join("\n"), self.date)
+    }
+}
+
+/// The context of `og/card`.
+#[derive(Serialize, Debug)]
+struct CardContext<'a> {
+    card: &'a Card,
+}
+
+/// Breaks the title into lines on word boundaries, since SVG text does not wrap on its own.
+/// Titles that need more than `MAX_LINES` lines are cut short with an ellipsis.
+fn wrap_title(title: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for word in title.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= LINE_LENGTH => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+
+    if lines.len() > MAX_LINES {
+        lines.truncate(MAX_LINES);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+
+    lines
+}
+
+fn rasterize(svg: &str) -> anyhow::Result<Vec<u8>> {
+    let options = usvg::Options {
+        fontdb: FONTS.clone(),
+        ..Default::default()
+    };
+
+    let tree = usvg::Tree::from_str(svg, &options)?;
+    let mut pixmap = Pixmap::new(WIDTH, HEIGHT).ok_or(anyhow!("could not allocate card pixmap"))?;
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+
+    Ok(pixmap.encode_png()?)
+}
+
+/// The social preview card for a post at `/og/{slug}.png`, rendered from the `og/card` template
+/// on first request and served from memory afterwards.
+#[instrument(skip(state))]
+pub async fn build_response(
+    Path(file): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let slug = file.strip_suffix(".png").ok_or(AppError::PageNotFound)?;
+
+    let publication = state.published_pages.snapshot();
+    let published_page = publication.get(slug).ok_or(AppError::PageNotFound)?;
+
+    let card = Card::new(published_page, state.site.title());
+    let fingerprint = card.fingerprint();
+
+    let png = match state.og_cards.get(slug, &fingerprint) {
+        Some(png) => png,
+        None => {
+            let templates = state.templates.load_full();
+            let png = spawn_blocking(move || {
+                let svg = templates.render("og/card", &CardContext { card: &card })?;
+                rasterize(&svg)
+            })
+            .await
+            .map_err(|e| anyhow!("card rendering task failed: {e}"))??;
+
+            let png = Bytes::from(png);
+            state.og_cards.insert(slug, fingerprint, png.clone());
+            png
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("image/png")),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=86400"),
+            ),
+        ],
+        png,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HEIGHT, MAX_LINES, WIDTH, build_response, wrap_title};
+    use crate::{
+        SharedState,
+        pages::{Frontmatter, PublishedPage, test_support::state},
+    };
+    use axum::{extract::Path, extract::State, response::IntoResponse};
+    use chrono::Utc;
+    use std::sync::Arc;
+
+    fn create_shared_state() -> Arc<SharedState> {
+        let page = PublishedPage {
+            path: "card".to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                slug: "card".to_string(),
+                title: "Running a <mail> server & friends".to_string(),
+                ..Default::default()
+            },
+        };
+
+        state(vec![page], Default::default())
+    }
+
+    #[test]
+    fn test_wrap_title() {
+        assert_eq!(
+            wrap_title("Building a Kubernetes mail server with Dovecot and Postfix"),
+            vec![
+                "Building a Kubernetes mail",
+                "server with Dovecot and",
+                "Postfix"
+            ]
+        );
+
+        let long = "word ".repeat(100);
+        let lines = wrap_title(&long);
+        assert_eq!(lines.len(), MAX_LINES);
+        assert!(lines[MAX_LINES - 1].ends_with('…'));
+    }
+
+    #[tokio::test]
+    async fn test_renders_png_card() {
+        let state = create_shared_state();
+
+        let response = build_response(Path("card.png".to_string()), State(state.clone()))
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(response.headers()["content-type"], "image/png");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.starts_with(b"\x89PNG"));
+
+        // The IHDR chunk holds the dimensions right after the signature and chunk header.
+        let width = u32::from_be_bytes(body[16..20].try_into().unwrap());
+        let height = u32::from_be_bytes(body[20..24].try_into().unwrap());
+        assert_eq!((width, height), (WIDTH, HEIGHT));
+
+        let cached = build_response(Path("card.png".to_string()), State(state))
+            .await
+            .unwrap()
+            .into_response();
+        let cached = axum::body::to_bytes(cached.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, cached);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_post_and_extension() {
+        let missing = build_response(
+            Path("missing.png".to_string()),
+            State(create_shared_state()),
+        );
+        assert!(missing.await.is_err());
+
+        let wrong_extension =
+            build_response(Path("card.jpg".to_string()), State(create_shared_state()));
+        assert!(wrong_extension.await.is_err());
+    }
+}
@@ -1,45 +1,243 @@
-use super::without_frontmatter;
-use crate::{AppError, SharedState};
-use anyhow::anyhow;
+use super::{
+    Publication, PublishedPage, ReadingTime, Series, TocEntry, cache::Cached, series::Navigation,
+    slugify,
+};
+use crate::{
+    AppError, SharedState,
+    site::{CacheRoute, SiteConfig},
+    templates::Head,
+};
 use axum::{
+    Extension,
     extract::{Path, State},
-    response::{Html, IntoResponse, Redirect},
+    response::{Html, IntoResponse, Redirect, Response},
 };
-use rust_web_common::templating::to_json;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
 use std::sync::Arc;
 
+#[derive(Serialize, Debug)]
+struct TagLink {
+    name: String,
+    slug: String,
+}
+
+/// Sharing and structured data metadata for a post, rendered into the layout's `<head>` as Open
+/// Graph and Twitter card tags and a `schema.org/BlogPosting` JSON-LD block.
+#[derive(Serialize, Debug)]
+struct Article {
+    published_time: String,
+    modified_time: Option<String>,
+    image: Option<String>,
+    tags: Vec<String>,
+    json_ld: String,
+}
+
+impl Article {
+    /// Only `published` pages have a generated card, so drafts without a cover image have no image.
+    fn new(site: &SiteConfig, page: &PublishedPage, url: &str, published: bool) -> Self {
+        let frontmatter = &page.frontmatter;
+        let published_time = page.published_at.to_rfc3339();
+        let modified_time = frontmatter
+            .revised_at
+            .map(|revised_at| revised_at.to_rfc3339());
+        // Posts without a cover image are shared with their generated card from `/og/{slug}.png`.
+        let image = match frontmatter.cover_image.as_deref() {
+            Some(image) => Some(absolute_url(site, image)),
+            None => published.then(|| site.url(&format!("/og/{}.png", frontmatter.slug))),
+        };
+
+        let mut json_ld = json!({
+            "@context": "https://schema.org",
+            "@type": "BlogPosting",
+            "headline": frontmatter.title,
+            "description": frontmatter.description,
+            "url": url,
+            "mainEntityOfPage": { "@type": "WebPage", "@id": url },
+            "datePublished": published_time,
+            "dateModified": modified_time.as_deref().unwrap_or(&published_time),
+            "author": { "@type": "Person", "name": site.author(), "url": site.base_url() },
+            "keywords": frontmatter.tags,
+        });
+        if let Some(image) = &image {
+            json_ld["image"] = json!(image);
+        }
+
+        Article {
+            published_time,
+            modified_time,
+            image,
+            tags: frontmatter.tags.clone(),
+            // The block is emitted unescaped inside `<script>`, so `<` must not be able to close it.
+            json_ld: json_ld.to_string().replace('<', "\\u003c"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Neighbor {
+    title: String,
+    slug: String,
+    published_at: DateTime<Utc>,
+}
+
+impl Neighbor {
+    fn new(page: &PublishedPage) -> Self {
+        Neighbor {
+            title: page.frontmatter.title.clone(),
+            slug: page.frontmatter.slug.clone(),
+            published_at: page.published_at,
+        }
+    }
+}
+
+/// The chronologically adjacent posts, linked at the foot of each post. Drafts have none.
+#[derive(Serialize, Debug, Default)]
+pub(super) struct Neighbors {
+    previous: Option<Neighbor>,
+    next: Option<Neighbor>,
+}
+
+impl Neighbors {
+    /// `pages` is ordered newest first, as returned by `get_all`, so the previous (older) post
+    /// follows `position` and the next (newer) one precedes it.
+    fn new(pages: &[Arc<PublishedPage>], position: usize) -> Self {
+        Neighbors {
+            previous: pages.get(position + 1).map(|page| Neighbor::new(page)),
+            next: position
+                .checked_sub(1)
+                .and_then(|index| pages.get(index))
+                .map(|page| Neighbor::new(page)),
+        }
+    }
+}
+
+/// The context of `pages/page`. `article` is also read by the layout.
+#[derive(Serialize, Debug)]
+struct PageContext<'a> {
+    content: &'a str,
+    published_at: DateTime<Utc>,
+    revised_at: Option<DateTime<Utc>>,
+    reading_time: ReadingTime,
+    tags: Vec<TagLink>,
+    series: Option<Navigation>,
+    toc: &'a [TocEntry],
+    neighbors: Neighbors,
+    article: Article,
+}
+
+fn absolute_url(site: &SiteConfig, path: &str) -> String {
+    if path.starts_with("https://") || path.starts_with("http://") {
+        path.to_string()
+    } else {
+        site.url(&format!("/{}", path.trim_start_matches('/')))
+    }
+}
+
 pub async fn build_response(
     Path(slug): Path<String>,
     State(state): State<Arc<SharedState>>,
-) -> Result<impl IntoResponse, AppError> {
-    let renderer = &state.renderer;
+) -> Result<Response, AppError> {
+    let publication = state.published_pages.snapshot();
+    let position = publication.position(&slug).ok_or(AppError::PageNotFound)?;
+    let pages = publication.pages();
+    let neighbors = Neighbors::new(pages, position);
 
-    let published_pages = state.published_pages.get_all()?;
-    let published_page = published_pages
-        .iter()
-        .find(|f| f.frontmatter.slug == slug)
-        .ok_or(AppError::PageNotFound)?;
+    let rendered = render(&state, &publication, &pages[position], neighbors)?;
+    let last_modified = last_modified(&publication, position, state.templates.load().loaded_at());
+    let cached = Cached::new(CacheRoute::Post, Some(last_modified));
 
-    let content = state.published_pages.read(&published_page.path).await?;
-    let content = without_frontmatter(&content).await?;
+    Ok((Extension(cached), Html(rendered)).into_response())
+}
 
-    let description = published_page.frontmatter.description.clone();
-    let published_at = published_page.published_at;
-    let revised_at = published_page.frontmatter.revised_at;
-    let title = published_page.frontmatter.title.clone();
+/// The series `page` belongs to, if it has been published.
+fn series_of<'a>(publication: &'a Publication, page: &PublishedPage) -> Option<&'a Series> {
+    let slug = slugify(page.frontmatter.series.as_deref()?);
+    publication
+        .series()
+        .iter()
+        .find(|series| series.slug == slug)
+}
 
-    renderer.insert("content", content)?;
-    renderer.insert("description", description)?;
-    renderer.insert("title", title)?;
-    renderer.insert("published_at", to_json(published_at))?;
-    renderer.insert("revised_at", to_json(revised_at))?;
-    renderer.insert("partial", "pages/page")?;
+/// When anything the post at `position` shows last changed: the post itself, its neighbors, the
+/// parts of its series, and the templates around it.
+fn last_modified(
+    publication: &Publication,
+    position: usize,
+    templates_loaded_at: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let pages = publication.pages();
+    let page = pages[position].as_ref();
+
+    let neighbors = position
+        .checked_sub(1)
+        .into_iter()
+        .chain([position + 1])
+        .filter_map(|index| pages.get(index))
+        .map(|neighbor| neighbor.as_ref());
+    let parts = series_of(publication, page)
+        .into_iter()
+        .flat_map(|series| publication.pages_at(&series.pages));
+
+    neighbors
+        .chain(parts)
+        .map(PublishedPage::updated_at)
+        .fold(page.updated_at().max(templates_loaded_at), DateTime::max)
+}
+
+/// Renders a single page through the `pages/page` template, with series navigation from
+/// `publication`. Shared with the draft preview route.
+pub(super) fn render(
+    state: &SharedState,
+    publication: &Publication,
+    published_page: &PublishedPage,
+    neighbors: Neighbors,
+) -> Result<String, AppError> {
+    let slug = &published_page.frontmatter.slug;
+
+    let tags: Vec<TagLink> = published_page
+        .frontmatter
+        .tags
+        .iter()
+        .map(|name| TagLink {
+            name: name.clone(),
+            slug: slugify(name),
+        })
+        .collect();
+
+    let series = series_of(publication, published_page)
+        .and_then(|series| Navigation::new(publication, series, slug));
+
+    let canonical_url = state.site.url(&format!("/post/{slug}"));
+    let context = PageContext {
+        content: &published_page.content,
+        published_at: published_page.published_at,
+        revised_at: published_page.frontmatter.revised_at,
+        reading_time: published_page.reading_time,
+        tags,
+        series,
+        toc: &published_page.toc,
+        neighbors,
+        article: Article::new(
+            &state.site,
+            published_page,
+            &canonical_url,
+            publication.get(slug).is_some(),
+        ),
+    };
+    let head = Head {
+        title: published_page.frontmatter.title.clone(),
+        description: published_page.frontmatter.description.clone(),
+        canonical_url,
+    };
 
-    let rendered = renderer
-        .render("layout")
-        .map_err(|e| anyhow!("could not render template: {e}"))?;
+    let rendered = state
+        .templates
+        .load()
+        .render_page("pages/page", head, &context)?;
 
-    Ok(Html(rendered))
+    Ok(rendered)
 }
 
 pub async fn remove_slash(Path(path_slug): Path<String>) -> Redirect {
@@ -51,51 +249,22 @@ pub async fn remove_slash(Path(path_slug): Path<String>) -> Redirect {
 
 #[cfg(test)]
 mod tests {
-    use super::{build_response, remove_slash};
+    use super::{build_response, last_modified, remove_slash};
     use crate::{
         SharedState,
-        pages::{Frontmatter, PublicationManager, PublishedPage},
+        pages::{
+            Content, Frontmatter, Publication, PublishedPage, ReadingTime, TocEntry, cache::Cached,
+            test_support::state,
+        },
+        site::CacheRoute,
     };
-    use anyhow::Result;
     use axum::{
         extract::{Path, State},
         http,
         response::IntoResponse,
     };
-    use chrono::{DateTime, Utc};
-    use std::{future::Future, pin::Pin, sync::Arc};
-
-    struct MockPublishedPages {
-        pages: Vec<PublishedPage>,
-    }
-
-    impl PublicationManager for MockPublishedPages {
-        fn get_all(&self) -> Result<Vec<PublishedPage>> {
-            Ok(self.pages.clone())
-        }
-
-        fn publish<'f>(
-            &'f mut self,
-        ) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>> {
-            Box::pin(async move { Ok(self.pages.len()) })
-        }
-
-        fn read<'f>(
-            &'f self,
-            _path: &'f str,
-        ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + Sync + 'f>> {
-            Box::pin(async move { Ok("this is the page content".to_string()) })
-        }
-    }
-
-    async fn create_shared_state(pages: Vec<PublishedPage>) -> Arc<SharedState> {
-        let mock_pages = MockPublishedPages { pages };
-        let renderer = rust_web_common::templating::Renderer::new("templates".to_string()).unwrap();
-        Arc::new(SharedState {
-            renderer,
-            published_pages: Box::new(mock_pages),
-        })
-    }
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::sync::Arc;
 
     fn create_page(
         path: &str,
@@ -106,6 +275,9 @@ mod tests {
     ) -> PublishedPage {
         PublishedPage {
             path: path.to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
             published_at: Utc::now(),
             frontmatter: Frontmatter {
                 description: description.to_string(),
@@ -114,6 +286,7 @@ mod tests {
                 revised_at,
                 slug: slug.to_string(),
                 title: title.to_string(),
+                ..Default::default()
             },
         }
     }
@@ -133,7 +306,7 @@ mod tests {
     #[tokio::test]
     async fn test_valid_response() {
         let pages = vec![create_page("test", "test", "Test", "test", None)];
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let body_string = execute_request_and_get_body("test", state).await;
 
         assert!(body_string.contains("Test"));
@@ -152,13 +325,70 @@ mod tests {
             Some(revised_date),
         )];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let body_string = execute_request_and_get_body("revised-page", state).await;
 
         assert!(body_string.contains("Revised Page"));
         assert!(body_string.contains("Revised page description"));
     }
 
+    #[tokio::test]
+    async fn test_build_response_last_modified_covers_templates() {
+        let revised_date = DateTime::parse_from_rfc3339("2023-01-15T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let pages = vec![create_page(
+            "test-revised",
+            "revised-page",
+            "Revised Page",
+            "Revised page description",
+            Some(revised_date),
+        )];
+        let state = state(pages, Default::default());
+
+        let response = build_response(Path("revised-page".to_string()), State(state.clone()))
+            .await
+            .unwrap();
+
+        let loaded_at = state.templates.load().loaded_at();
+        assert_eq!(
+            response.extensions().get::<Cached>(),
+            Some(&Cached::new(CacheRoute::Post, Some(loaded_at)))
+        );
+    }
+
+    #[test]
+    fn test_last_modified_covers_neighbors_and_series_only() {
+        let day = |day: u32| Utc.with_ymd_and_hms(2024, 3, day, 0, 0, 0).unwrap();
+        let dated = |slug: &str, published: u32, series: Option<&str>| {
+            let mut page = create_page(slug, slug, slug, slug, None);
+            page.published_at = day(published);
+            page.frontmatter.series = series.map(str::to_string);
+            page
+        };
+        let publication = |pages: Vec<PublishedPage>| {
+            let content = Content {
+                published: pages,
+                ..Default::default()
+            };
+            Publication::new(content, Utc::now())
+        };
+
+        // Newest first: day 5, 4, 3, 2, 1. The post from day 2 sits between days 3 and 1.
+        let standalone = publication((1..=5).map(|n| dated(&format!("p{n}"), n, None)).collect());
+        let position = standalone.position("p2").unwrap();
+        assert_eq!(last_modified(&standalone, position, day(1)), day(3));
+        assert_eq!(last_modified(&standalone, position, day(4)), day(4));
+
+        let in_series = publication(
+            (1..=5)
+                .map(|n| dated(&format!("p{n}"), n, [2, 5].contains(&n).then_some("Mail")))
+                .collect(),
+        );
+        let position = in_series.position("p2").unwrap();
+        assert_eq!(last_modified(&in_series, position, day(1)), day(5));
+    }
+
     #[tokio::test]
     async fn test_build_response_page_not_found() {
         let pages = vec![create_page(
@@ -168,7 +398,7 @@ mod tests {
             "Existing page",
             None,
         )];
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let path = Path("non-existent".to_string());
 
         let result = build_response(path, State(state)).await;
@@ -185,7 +415,7 @@ mod tests {
             None,
         )];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let body_string = execute_request_and_get_body("title-test", state).await;
 
         assert!(body_string.contains("My Great Article"));
@@ -199,7 +429,7 @@ mod tests {
             create_page("test-page3", "page3", "Page 3", "Page 3 description", None),
         ];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, Default::default());
         let body_string = execute_request_and_get_body("page2", state).await;
 
         // Should contain the correct page content
@@ -210,9 +440,162 @@ mod tests {
         assert!(!body_string.contains("Page 3 description"));
     }
 
+    #[tokio::test]
+    async fn test_build_response_renders_tags() {
+        let mut page = create_page("test-tags", "tags", "Tagged", "Tagged page", None);
+        page.frontmatter.tags = vec!["Mail server".to_string()];
+
+        let state = state(vec![page], Default::default());
+        let body_string = execute_request_and_get_body("tags", state).await;
+
+        assert!(body_string.contains("href=\"/tags/mail-server\""));
+        assert!(body_string.contains("Mail server"));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_renders_series_box() {
+        let mut part_one = create_page("part-one", "part-one", "Part One", "First part", None);
+        part_one.frontmatter.series = Some("Cloud Run deploys".to_string());
+        part_one.frontmatter.series_order = Some(1);
+        let mut part_two = create_page("part-two", "part-two", "Part Two", "Second part", None);
+        part_two.frontmatter.series = Some("Cloud Run deploys".to_string());
+        part_two.frontmatter.series_order = Some(2);
+
+        let state = state(vec![part_two, part_one], Default::default());
+        let body_string = execute_request_and_get_body("part-one", state).await;
+
+        assert!(body_string.contains("href=\"/series/cloud-run-deploys\""));
+        assert!(body_string.contains("Part 1 of 2"));
+        assert!(body_string.contains("rel=\"next\""));
+        assert!(!body_string.contains("rel=\"prev\""));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_renders_toc() {
+        let mut page = create_page("test-toc", "toc", "Guide", "A long guide", None);
+        page.toc = vec![TocEntry {
+            title: "Install Dovecot".to_string(),
+            id: "install-dovecot".to_string(),
+            level: 2,
+            children: vec![TocEntry {
+                title: "Configure TLS".to_string(),
+                id: "configure-tls".to_string(),
+                level: 3,
+                children: Vec::new(),
+            }],
+        }];
+
+        let state = state(vec![page], Default::default());
+        let body_string = execute_request_and_get_body("toc", state).await;
+
+        assert!(body_string.contains("aria-label=\"Table of contents\""));
+        assert!(body_string.contains("href=\"#install-dovecot\""));
+        assert!(body_string.contains("href=\"#configure-tls\""));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_renders_reading_time() {
+        let mut page = create_page("test-reading", "reading", "Reading", "Reading time", None);
+        page.reading_time = ReadingTime {
+            words: 1234,
+            minutes: 7,
+        };
+
+        let state = state(vec![page], Default::default());
+        let body_string = execute_request_and_get_body("reading", state).await;
+
+        assert!(body_string.contains("7 min read (1234 words)"));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_renders_canonical_link() {
+        let pages = vec![create_page("test", "canonical", "Test", "test", None)];
+        let state = state(pages, Default::default());
+        let body_string = execute_request_and_get_body("canonical", state).await;
+
+        assert!(body_string.contains(
+            "<link rel=\"canonical\" href=\"https://corybuecker.com/post/canonical\" />"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_renders_sharing_metadata() {
+        let revised_at = DateTime::parse_from_rfc3339("2024-02-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut page = create_page(
+            "test-meta",
+            "meta",
+            "Sharing </script>",
+            "Shared description",
+            Some(revised_at),
+        );
+        page.frontmatter.cover_image = Some("/images/cover.png".to_string());
+        page.frontmatter.tags = vec!["Kubernetes".to_string()];
+
+        let state = state(vec![page], Default::default());
+        let body_string = execute_request_and_get_body("meta", state).await;
+
+        assert!(body_string.contains("<meta property=\"og:type\" content=\"article\" />"));
+        assert!(body_string.contains(
+            "<meta property=\"og:image\" content=\"https://corybuecker.com/images/cover.png\" />"
+        ));
+        assert!(
+            body_string.contains("<meta name=\"twitter:card\" content=\"summary_large_image\" />")
+        );
+        assert!(body_string.contains("property=\"article:modified_time\""));
+        assert!(body_string.contains("content=\"2024-02-01T00:00:00+00:00\""));
+        assert!(body_string.contains("<meta property=\"article:tag\" content=\"Kubernetes\" />"));
+        assert!(body_string.contains("\"@type\":\"BlogPosting\""));
+        assert!(body_string.contains("\"dateModified\":\"2024-02-01T00:00:00+00:00\""));
+        assert!(body_string.contains("\"headline\":\"Sharing \\u003c/script>\""));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_without_cover_image() {
+        let pages = vec![create_page("test", "test", "Test", "test", None)];
+        let state = state(pages, Default::default());
+        let body_string = execute_request_and_get_body("test", state).await;
+
+        assert!(body_string.contains(
+            "<meta property=\"og:image\" content=\"https://corybuecker.com/og/test.png\" />"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_without_toc() {
+        let pages = vec![create_page("test", "test", "Test", "test", None)];
+        let state = state(pages, Default::default());
+        let body_string = execute_request_and_get_body("test", state).await;
+
+        assert!(!body_string.contains("Table of contents"));
+    }
+
+    #[tokio::test]
+    async fn test_build_response_renders_neighbors() {
+        let pages = vec![
+            create_page("newest", "newest", "Newest", "Newest post", None),
+            create_page("middle", "middle", "Middle", "Middle post", None),
+            create_page("oldest", "oldest", "Oldest", "Oldest post", None),
+        ];
+        let state = state(pages, Default::default());
+
+        let body_string = execute_request_and_get_body("middle", state.clone()).await;
+        assert!(body_string.contains("aria-label=\"More posts\""));
+        assert!(body_string.contains("href=\"/post/oldest\""));
+        assert!(body_string.contains("href=\"/post/newest\""));
+
+        let body_string = execute_request_and_get_body("newest", state.clone()).await;
+        assert!(body_string.contains("href=\"/post/middle\""));
+        assert!(!body_string.contains("Newer post"));
+
+        let body_string = execute_request_and_get_body("oldest", state).await;
+        assert!(!body_string.contains("Older post"));
+    }
+
     #[tokio::test]
     async fn test_build_response_empty_pages_list() {
-        let state = create_shared_state(vec![]).await;
+        let state = state(vec![], Default::default());
         let path = Path("any-slug".to_string());
 
         let result = build_response(path, State(state)).await;
@@ -0,0 +1,221 @@
+use super::page;
+use crate::{AppError, SharedState};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderValue, header},
+    response::{Html, IntoResponse},
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::sync::Arc;
+
+const X_ROBOTS_TAG: &str = "X-Robots-Tag";
+
+/// Signs and verifies draft preview tokens with an HMAC of the draft's slug. Rotating the secret
+/// revokes every outstanding preview link.
+#[derive(Clone)]
+pub struct PreviewSigner {
+    secret: Vec<u8>,
+}
+
+impl PreviewSigner {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    fn mac(&self, slug: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(slug.as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, slug: &str) -> String {
+        hex::encode(self.mac(slug).finalize().into_bytes())
+    }
+
+    pub fn verify(&self, slug: &str, token: &str) -> bool {
+        match hex::decode(token) {
+            Ok(token) => self.mac(slug).verify_slice(&token).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn preview_path(&self, slug: &str) -> String {
+        format!("/preview/{slug}?token={}", self.sign(slug))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PreviewQuery {
+    token: Option<String>,
+}
+
+pub async fn build_response(
+    Path(slug): Path<String>,
+    Query(query): Query<PreviewQuery>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<impl IntoResponse, AppError> {
+    // Missing secrets, bad tokens and unknown drafts all look like a plain 404 so previews cannot
+    // be used to discover draft slugs.
+    let signer = state
+        .preview_signer
+        .as_ref()
+        .ok_or(AppError::PageNotFound)?;
+    let token = query.token.ok_or(AppError::PageNotFound)?;
+
+    if !signer.verify(&slug, &token) {
+        return Err(AppError::PageNotFound);
+    }
+
+    let publication = state.published_pages.snapshot();
+    let draft = publication.get_draft(&slug).ok_or(AppError::PageNotFound)?;
+
+    let rendered = page::render(&state, &publication, draft, Default::default())?;
+
+    Ok((
+        [
+            (X_ROBOTS_TAG, HeaderValue::from_static("noindex, nofollow")),
+            (
+                header::CACHE_CONTROL.as_str(),
+                HeaderValue::from_static("private, no-store"),
+            ),
+        ],
+        Html(rendered),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PreviewQuery, PreviewSigner, build_response};
+    use crate::{
+        SharedState,
+        pages::{
+            Frontmatter, PublishedPage,
+            test_support::{MockPublishedPages, shared_state},
+        },
+    };
+    use axum::{
+        extract::{Path, Query, State},
+        response::IntoResponse,
+    };
+    use chrono::Utc;
+    use std::sync::Arc;
+
+    fn create_page(slug: &str, title: &str) -> PublishedPage {
+        PublishedPage {
+            path: slug.to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                description: format!("{title} description"),
+                preview: format!("{title} preview"),
+                slug: slug.to_string(),
+                title: title.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn create_shared_state(signer: Option<PreviewSigner>) -> Arc<SharedState> {
+        let published_pages = MockPublishedPages {
+            pages: vec![create_page("published", "Published")],
+            drafts: vec![create_page("draft", "Draft Post")],
+            ..Default::default()
+        };
+
+        Arc::new(SharedState {
+            preview_signer: signer,
+            ..shared_state(published_pages, Default::default())
+        })
+    }
+
+    fn query(token: Option<String>) -> Query<PreviewQuery> {
+        Query(PreviewQuery { token })
+    }
+
+    #[test]
+    fn test_signer_round_trip() {
+        let signer = PreviewSigner::new("secret");
+        let token = signer.sign("draft");
+
+        assert!(signer.verify("draft", &token));
+        assert!(!signer.verify("other", &token));
+        assert!(!PreviewSigner::new("rotated").verify("draft", &token));
+        assert!(!signer.verify("draft", "not-hex"));
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_renders_draft_with_noindex() {
+        let signer = PreviewSigner::new("secret");
+        let token = signer.sign("draft");
+        let state = create_shared_state(Some(signer));
+
+        let response = build_response(Path("draft".to_string()), query(Some(token)), State(state))
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(
+            response.headers().get("X-Robots-Tag").unwrap(),
+            "noindex, nofollow"
+        );
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("Draft Post"));
+        // Drafts have no generated card to share.
+        assert!(!body.contains("og:image"));
+        assert!(!body.contains("/og/draft.png"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_is_not_found() {
+        let state = create_shared_state(Some(PreviewSigner::new("secret")));
+        let token = PreviewSigner::new("wrong").sign("draft");
+
+        let result =
+            build_response(Path("draft".to_string()), query(Some(token)), State(state)).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_token_is_not_found() {
+        let state = create_shared_state(Some(PreviewSigner::new("secret")));
+
+        let result = build_response(Path("draft".to_string()), query(None), State(state)).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_previews_disabled_without_secret() {
+        let token = PreviewSigner::new("secret").sign("draft");
+        let state = create_shared_state(None);
+
+        let result =
+            build_response(Path("draft".to_string()), query(Some(token)), State(state)).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_published_pages_are_not_previewed() {
+        let signer = PreviewSigner::new("secret");
+        let token = signer.sign("published");
+        let state = create_shared_state(Some(signer));
+
+        let result = build_response(
+            Path("published".to_string()),
+            query(Some(token)),
+            State(state),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
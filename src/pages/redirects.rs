@@ -0,0 +1,332 @@
+use super::PublishedPage;
+use crate::{AppError, SharedState};
+use anyhow::{Result, anyhow};
+use axum::{
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::Path,
+    sync::Arc,
+};
+use tokio::fs;
+
+/// Site-wide redirects for URLs that never belonged to a single post, such as paths from an
+/// earlier version of the site. The file is optional.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RedirectsFile {
+    #[serde(default)]
+    redirects: BTreeMap<String, String>,
+}
+
+/// Reads the redirects file into a map from old path to new location, keyed by normalized path.
+pub(super) async fn load(path: &Path) -> Result<BTreeMap<String, String>> {
+    let contents = match fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(anyhow!("could not read {}: {err}", path.display())),
+    };
+
+    parse(&path.display().to_string(), &contents)
+}
+
+fn parse(path: &str, contents: &str) -> Result<BTreeMap<String, String>> {
+    let file: RedirectsFile =
+        toml::from_str(contents).map_err(|e| anyhow!("{path}: invalid redirects: {e}"))?;
+
+    file.redirects
+        .into_iter()
+        .map(|(from, to)| {
+            if !from.starts_with('/') {
+                return Err(anyhow!("{path}: redirect `{from}` must start with `/`"));
+            }
+            if !(to.starts_with('/') || to.starts_with("https://") || to.starts_with("http://")) {
+                return Err(anyhow!(
+                    "{path}: redirect target `{to}` must be a path or an http(s) URL"
+                ));
+            }
+            Ok((normalize(&from).to_string(), to))
+        })
+        .collect()
+}
+
+/// Trailing slashes are ignored so that `/old/` and `/old` redirect alike.
+fn normalize(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Paths the router in `main.rs` always answers, so that a redirect from one of them could never
+/// fire. Redirects under routes with parameters, such as `/tags/{tag}`, do fire, for whatever the
+/// route has no page for.
+const ROUTES: &[&str] = &[
+    "/",
+    "/archive",
+    "/search",
+    "/api/search",
+    "/tags",
+    "/sitemap.xml",
+    "/feed.xml",
+    "/atom.xml",
+    "/feed.json",
+    "/healthcheck",
+];
+
+/// Served straight from disk, whose misses never reach the redirect table.
+const FILES: &str = "/images";
+
+/// Whether the router answers `from` before the redirect table is ever consulted.
+fn routed(from: &str) -> bool {
+    ROUTES.contains(&from)
+        || from
+            .strip_prefix(FILES)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The old path an alias stands for. Bare slugs are former `/post/{slug}` URLs, while aliases that
+/// start with `/` are full paths.
+fn alias_path(alias: &str) -> String {
+    if alias.starts_with('/') {
+        normalize(alias).to_string()
+    } else {
+        format!("/post/{alias}")
+    }
+}
+
+/// Rejects aliases and redirects that would shadow a live post or one of the site's own routes,
+/// or that claim the same old path twice. `pages` should include scheduled pages so that a release
+/// can never introduce a clash.
+pub(super) fn validate(
+    pages: &[PublishedPage],
+    redirects_file: &Path,
+    redirects: &BTreeMap<String, String>,
+) -> Result<()> {
+    let live: HashMap<String, &str> = pages
+        .iter()
+        .map(|page| {
+            let slug = page.frontmatter.slug.as_str();
+            (format!("/post/{slug}"), slug)
+        })
+        .collect();
+    let mut claimed: HashMap<String, &str> = HashMap::new();
+
+    for page in pages {
+        for alias in &page.frontmatter.aliases {
+            let from = alias_path(alias);
+
+            if let Some(slug) = live.get(&from) {
+                return Err(anyhow!(
+                    "{}: alias `{alias}` collides with the live post `{slug}`",
+                    page.path
+                ));
+            }
+            if routed(&from) {
+                return Err(anyhow!(
+                    "{}: alias `{alias}` collides with a route of the site",
+                    page.path
+                ));
+            }
+            if let Some(other) = claimed.insert(from, &page.path) {
+                return Err(anyhow!(
+                    "{}: alias `{alias}` is also claimed by {other}",
+                    page.path
+                ));
+            }
+        }
+    }
+
+    for from in redirects.keys() {
+        if let Some(slug) = live.get(from) {
+            return Err(anyhow!(
+                "{}: redirect `{from}` collides with the live post `{slug}`",
+                redirects_file.display()
+            ));
+        }
+        if routed(from) {
+            return Err(anyhow!(
+                "{}: redirect `{from}` collides with a route of the site",
+                redirects_file.display()
+            ));
+        }
+        if let Some(other) = claimed.get(from.as_str()) {
+            return Err(anyhow!(
+                "{}: redirect `{from}` is also an alias in {other}",
+                redirects_file.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the redirect table for the published pages. Aliases of scheduled pages only take effect
+/// once the page goes live.
+pub(super) fn collect(
+    pages: &[Arc<PublishedPage>],
+    redirects: &BTreeMap<String, String>,
+) -> HashMap<String, String> {
+    let mut table: HashMap<String, String> = redirects.clone().into_iter().collect();
+
+    for page in pages {
+        for alias in &page.frontmatter.aliases {
+            table.insert(
+                alias_path(alias),
+                format!("/post/{}", page.frontmatter.slug),
+            );
+        }
+    }
+
+    table
+}
+
+/// Issues a 301 for `path` if it has moved.
+pub(super) fn find(state: &SharedState, path: &str) -> Result<Option<Response>, AppError> {
+    let publication = state.published_pages.snapshot();
+    let Some(location) = publication.get_redirect(normalize(path)) else {
+        return Ok(None);
+    };
+    let location = HeaderValue::try_from(location)
+        .map_err(|e| anyhow!("invalid redirect location for {path}: {e}"))?;
+
+    Ok(Some(
+        (
+            StatusCode::MOVED_PERMANENTLY,
+            [(header::LOCATION, location)],
+        )
+            .into_response(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect, find, parse, validate};
+    use crate::{
+        SharedState,
+        pages::{
+            Frontmatter, PublishedPage,
+            test_support::{MockPublishedPages, shared_state},
+        },
+    };
+    use axum::http::StatusCode;
+    use chrono::Utc;
+    use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+    fn create_page(slug: &str, aliases: &[&str]) -> PublishedPage {
+        PublishedPage {
+            path: format!("content/{slug}.md"),
+            content: String::new(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+                slug: slug.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn create_shared_state(redirects: BTreeMap<String, String>) -> Arc<SharedState> {
+        let published_pages = MockPublishedPages {
+            redirects,
+            ..Default::default()
+        };
+
+        Arc::new(shared_state(published_pages, Default::default()))
+    }
+
+    #[test]
+    fn test_parse_redirects_file() {
+        let redirects = parse(
+            "redirects.toml",
+            "[redirects]\n\"/2019/05/old-post/\" = \"/post/new-post\"",
+        )
+        .unwrap();
+        assert_eq!(redirects["/2019/05/old-post"], "/post/new-post");
+
+        assert!(parse("redirects.toml", "[redirects]\n\"old\" = \"/post/new\"").is_err());
+        assert!(parse("redirects.toml", "[redirects]\n\"/old\" = \"new\"").is_err());
+        assert!(parse("redirects.toml", "[moved]\n\"/old\" = \"/new\"").is_err());
+    }
+
+    #[test]
+    fn test_collect_aliases_and_file() {
+        let pages = vec![Arc::new(create_page(
+            "new-name",
+            &["old-name", "/blog/old-name/"],
+        ))];
+        let file = BTreeMap::from([("/about".to_string(), "/".to_string())]);
+
+        let table = collect(&pages, &file);
+
+        assert_eq!(table["/post/old-name"], "/post/new-name");
+        assert_eq!(table["/blog/old-name"], "/post/new-name");
+        assert_eq!(table["/about"], "/");
+    }
+
+    #[test]
+    fn test_validate_rejects_collisions() {
+        let file_path = Path::new("redirects.toml");
+        let file = BTreeMap::new();
+
+        let shadowing = vec![create_page("first", &[]), create_page("second", &["first"])];
+        let err = validate(&shadowing, file_path, &file)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("content/second.md"));
+        assert!(err.contains("live post `first`"));
+
+        let duplicate = vec![
+            create_page("first", &["old"]),
+            create_page("second", &["old"]),
+        ];
+        assert!(validate(&duplicate, file_path, &file).is_err());
+
+        let pages = vec![create_page("first", &["old"])];
+        let file = BTreeMap::from([("/post/first".to_string(), "/".to_string())]);
+        assert!(validate(&pages, file_path, &file).is_err());
+        let file = BTreeMap::from([("/post/old".to_string(), "/".to_string())]);
+        assert!(validate(&pages, file_path, &file).is_err());
+        assert!(validate(&pages, file_path, &BTreeMap::new()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_routes() {
+        let file_path = Path::new("redirects.toml");
+
+        for from in ["/", "/search", "/tags", "/feed.xml", "/images/cover.png"] {
+            let file = BTreeMap::from([(from.to_string(), "/post/first".to_string())]);
+            let err = validate(&[], file_path, &file).unwrap_err().to_string();
+            assert!(err.contains("route of the site"), "{from}");
+        }
+
+        let routed = vec![create_page("first", &["/archive"])];
+        assert!(validate(&routed, file_path, &BTreeMap::new()).is_err());
+
+        // Paths under routes with parameters redirect whatever the route has no page for.
+        for from in ["/tags/old-name", "/archive/2019", "/imagery"] {
+            let file = BTreeMap::from([(from.to_string(), "/post/first".to_string())]);
+            assert!(validate(&[], file_path, &file).is_ok(), "{from}");
+        }
+    }
+
+    #[test]
+    fn test_find_redirects_permanently() {
+        let state = create_shared_state(BTreeMap::from([(
+            "/post/old-name".to_string(),
+            "/post/new-name".to_string(),
+        )]));
+
+        let response = find(&state, "/post/old-name/").unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()["location"], "/post/new-name");
+
+        assert!(find(&state, "/missing").unwrap().is_none());
+    }
+}
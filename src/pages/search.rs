@@ -0,0 +1,491 @@
+use super::PublishedPage;
+use crate::{AppError, SharedState, templates::Head};
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::Html,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    sync::Arc,
+};
+
+const MAX_RESULTS: usize = 20;
+
+/// Roughly how many bytes of text a snippet shows, and how many of them come before the first
+/// match.
+const SNIPPET_LENGTH: usize = 200;
+const SNIPPET_CONTEXT: usize = 60;
+
+const TITLE_WEIGHT: u32 = 5;
+const DESCRIPTION_WEIGHT: u32 = 2;
+const BODY_WEIGHT: u32 = 1;
+
+/// Whole-word matches count this many times more than prefix matches, so a search for `rust`
+/// ranks posts about Rust above posts that only mention `rustup`.
+const EXACT_MATCH_BOOST: u32 = 2;
+
+/// Part of a snippet. Highlighted parts are the words that matched the query.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Segment {
+    text: String,
+    highlight: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchResult {
+    pub title: String,
+    pub slug: String,
+    pub published_at: DateTime<Utc>,
+    pub snippet: Vec<Segment>,
+}
+
+#[derive(Debug)]
+struct Document {
+    title: String,
+    slug: String,
+    description: String,
+    published_at: DateTime<Utc>,
+    text: String,
+}
+
+/// An inverted index over the titles, descriptions and body text of published pages, built each
+/// time pages are published. Terms are kept sorted so that prefix matches are a range scan.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    documents: Vec<Document>,
+    terms: BTreeMap<String, Vec<(usize, u32)>>,
+}
+
+impl SearchIndex {
+    pub fn new(pages: &[Arc<PublishedPage>]) -> Self {
+        let mut index = SearchIndex::default();
+
+        for page in pages {
+            let document = index.documents.len();
+            let text = strip_tags(&page.content);
+
+            let mut weights: HashMap<String, u32> = HashMap::new();
+            for (field, weight) in [
+                (page.frontmatter.title.as_str(), TITLE_WEIGHT),
+                (page.frontmatter.description.as_str(), DESCRIPTION_WEIGHT),
+                (text.as_str(), BODY_WEIGHT),
+            ] {
+                for term in terms(field) {
+                    *weights.entry(term).or_default() += weight;
+                }
+            }
+
+            for (term, weight) in weights {
+                index
+                    .terms
+                    .entry(term)
+                    .or_default()
+                    .push((document, weight));
+            }
+
+            index.documents.push(Document {
+                title: page.frontmatter.title.clone(),
+                slug: page.frontmatter.slug.clone(),
+                description: page.frontmatter.description.clone(),
+                published_at: page.published_at,
+                text,
+            });
+        }
+
+        index
+    }
+
+    /// Finds pages containing every word of the query, each either as a whole word or as the
+    /// start of one. Results are ranked by how often and where the words appear, newest first on
+    /// ties.
+    pub fn search(&self, query: &str) -> Vec<SearchResult> {
+        let mut query: Vec<String> = terms(query).collect();
+        query.sort();
+        query.dedup();
+
+        let mut scores: Option<HashMap<usize, u32>> = None;
+
+        for term in &query {
+            let mut matches: HashMap<usize, u32> = HashMap::new();
+
+            let candidates = self
+                .terms
+                .range::<str, _>((Bound::Included(term.as_str()), Bound::Unbounded))
+                .take_while(|(candidate, _)| candidate.starts_with(term.as_str()));
+
+            for (candidate, postings) in candidates {
+                let boost = if candidate == term {
+                    EXACT_MATCH_BOOST
+                } else {
+                    1
+                };
+                for (document, weight) in postings {
+                    *matches.entry(*document).or_default() += weight * boost;
+                }
+            }
+
+            scores = Some(match scores {
+                None => matches,
+                Some(mut scores) => {
+                    scores.retain(|document, _| matches.contains_key(document));
+                    for (document, score) in scores.iter_mut() {
+                        *score += matches[document];
+                    }
+                    scores
+                }
+            });
+        }
+
+        let mut ranked: Vec<(usize, u32)> = scores.unwrap_or_default().into_iter().collect();
+        ranked.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.cmp(a_score).then_with(|| {
+                self.documents[*b]
+                    .published_at
+                    .cmp(&self.documents[*a].published_at)
+            })
+        });
+
+        ranked
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|(document, _)| {
+                let document = &self.documents[document];
+                let snippet = snippet(&document.text, &query)
+                    .or_else(|| snippet(&document.description, &query))
+                    .unwrap_or_else(|| {
+                        vec![Segment {
+                            text: document.description.clone(),
+                            highlight: false,
+                        }]
+                    });
+
+                SearchResult {
+                    title: document.title.clone(),
+                    slug: document.slug.clone(),
+                    published_at: document.published_at,
+                    snippet,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Byte ranges of the words in `text`, where a word is a run of letters and digits.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+
+    for (index, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(from)) => {
+                spans.push((from, index));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        spans.push((from, text.len()));
+    }
+
+    spans
+}
+
+fn terms(text: &str) -> impl Iterator<Item = String> {
+    words(text)
+        .into_iter()
+        .map(|(start, end)| text[start..end].to_lowercase())
+}
+
+/// Reduces rendered post HTML to its text. Tags become spaces, which are then collapsed, and the
+/// entities that the Markdown renderer emits are decoded.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+/// An excerpt of `text` around the first word matching the query, with every matching word in it
+/// highlighted. `None` when no word matches.
+fn snippet(text: &str, query: &[String]) -> Option<Vec<Segment>> {
+    let spans = words(text);
+    let matches = |(start, end): (usize, usize)| {
+        let word = text[start..end].to_lowercase();
+        query.iter().any(|term| word.starts_with(term.as_str()))
+    };
+
+    let first = spans.iter().position(|span| matches(*span))?;
+    let from = spans[..=first]
+        .iter()
+        .find(|(start, _)| start + SNIPPET_CONTEXT >= spans[first].0)
+        .map_or(spans[first].0, |(start, _)| *start);
+    let to = spans[first..]
+        .iter()
+        .take_while(|(_, end)| *end <= from + SNIPPET_LENGTH)
+        .last()
+        .map_or(spans[first].1, |(_, end)| *end);
+
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut push = |text: &str, highlight: bool| match segments.last_mut() {
+        Some(last) if !last.highlight && !highlight => last.text.push_str(text),
+        _ => segments.push(Segment {
+            text: text.to_string(),
+            highlight,
+        }),
+    };
+
+    if from > 0 {
+        push("…", false);
+    }
+
+    let mut cursor = from;
+    for &(start, end) in spans
+        .iter()
+        .filter(|(start, end)| *start >= from && *end <= to)
+    {
+        if matches((start, end)) {
+            push(&text[cursor..start], false);
+            push(&text[start..end], true);
+            cursor = end;
+        }
+    }
+    push(&text[cursor..to], false);
+
+    if to < text.len() {
+        push("…", false);
+    }
+
+    segments.retain(|segment| !segment.text.is_empty());
+
+    Some(segments)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+/// The context of `pages/search`.
+#[derive(Serialize, Debug)]
+struct SearchContext<'a> {
+    query: &'a str,
+    results: Vec<SearchResult>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResponse {
+    query: String,
+    results: Vec<SearchResult>,
+}
+
+pub async fn page(
+    Query(query): Query<SearchQuery>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Html<String>, AppError> {
+    let query = query.q.trim();
+
+    let (title, results) = if query.is_empty() {
+        ("Search".to_string(), Vec::new())
+    } else {
+        (
+            format!("Search results for {query}"),
+            state.published_pages.snapshot().search(query),
+        )
+    };
+
+    let head = Head {
+        title,
+        description: "Search every post on the blog.".to_string(),
+        canonical_url: state.site.url("/search"),
+    };
+
+    let rendered = state.templates.load().render_page(
+        "pages/search",
+        head,
+        &SearchContext { query, results },
+    )?;
+
+    Ok(Html(rendered))
+}
+
+/// The same search as JSON, for clients that render their own results.
+pub async fn api(
+    Query(query): Query<SearchQuery>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Json<SearchResponse>, AppError> {
+    let query = query.q.trim().to_string();
+    let results = if query.is_empty() {
+        Vec::new()
+    } else {
+        state.published_pages.snapshot().search(&query)
+    };
+
+    Ok(Json(SearchResponse { query, results }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchIndex, SearchQuery, Segment, api, page, snippet, strip_tags};
+    use crate::{
+        SharedState,
+        pages::{Frontmatter, PublishedPage, test_support::state},
+    };
+    use axum::extract::{Query, State};
+    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+
+    fn create_page(slug: &str, title: &str, content: &str, age: i64) -> PublishedPage {
+        PublishedPage {
+            path: slug.to_string(),
+            content: content.to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now() - Duration::days(age),
+            frontmatter: Frontmatter {
+                description: format!("About {title}"),
+                slug: slug.to_string(),
+                title: title.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn create_pages() -> Vec<PublishedPage> {
+        vec![
+            create_page(
+                "mail",
+                "Running a mail server",
+                "<p>Dovecot and Postfix on <code>Kubernetes</code> &amp; friends.</p>",
+                1,
+            ),
+            create_page(
+                "kubernetes",
+                "Kubernetes at home",
+                "<p>A small cluster with kubectl.</p>",
+                2,
+            ),
+            create_page("rust", "Rust web services", "<p>Axum and rustup.</p>", 3),
+        ]
+    }
+
+    fn create_shared_state() -> Arc<SharedState> {
+        state(create_pages(), Default::default())
+    }
+
+    fn slugs(index: &SearchIndex, query: &str) -> Vec<String> {
+        index
+            .search(query)
+            .into_iter()
+            .map(|result| result.slug)
+            .collect()
+    }
+
+    #[test]
+    fn test_strip_tags() {
+        assert_eq!(
+            strip_tags("<p>Dovecot &amp; <code>&lt;Postfix&gt;</code></p>\n<p>Done</p>"),
+            "Dovecot & <Postfix> Done"
+        );
+    }
+
+    #[test]
+    fn test_search_ranks_title_matches_first() {
+        let index = SearchIndex::new(&create_pages().into_iter().map(Arc::new).collect::<Vec<_>>());
+
+        assert_eq!(slugs(&index, "kubernetes"), vec!["kubernetes", "mail"]);
+        assert_eq!(slugs(&index, "KUBERNETES"), vec!["kubernetes", "mail"]);
+    }
+
+    #[test]
+    fn test_search_matches_prefixes_and_every_word() {
+        let index = SearchIndex::new(&create_pages().into_iter().map(Arc::new).collect::<Vec<_>>());
+
+        assert_eq!(slugs(&index, "kube"), vec!["kubernetes", "mail"]);
+        assert_eq!(slugs(&index, "rust"), vec!["rust"]);
+        assert_eq!(slugs(&index, "kube dovecot"), vec!["mail"]);
+        assert!(slugs(&index, "kube axum").is_empty());
+        assert!(slugs(&index, "  ").is_empty());
+    }
+
+    #[test]
+    fn test_snippet_highlights_matches() {
+        let query = vec!["postfix".to_string()];
+        let segments = snippet("Dovecot and Postfix together", &query).unwrap();
+
+        assert_eq!(
+            segments,
+            vec![
+                Segment {
+                    text: "Dovecot and ".to_string(),
+                    highlight: false
+                },
+                Segment {
+                    text: "Postfix".to_string(),
+                    highlight: true
+                },
+                Segment {
+                    text: " together".to_string(),
+                    highlight: false
+                },
+            ]
+        );
+
+        let long = format!("{} needle {}", "word ".repeat(100), "word ".repeat(100));
+        let segments = snippet(&long, &["needle".to_string()]).unwrap();
+        assert!(segments[0].text.starts_with('…'));
+        assert!(segments[2].text.ends_with('…'));
+        assert!(segments[2].text.len() < long.len() / 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_page_escapes_and_highlights() {
+        let query = Query(SearchQuery {
+            q: "<friends>".to_string(),
+        });
+        let response = page(query, State(create_shared_state())).await.unwrap();
+
+        assert!(response.0.contains("href=\"/post/mail\""));
+        assert!(response.0.contains("<mark>friends</mark>"));
+        assert!(response.0.contains("value=\"&lt;friends&gt;\""));
+    }
+
+    #[tokio::test]
+    async fn test_search_api() {
+        let query = Query(SearchQuery {
+            q: "kubectl".to_string(),
+        });
+        let response = api(query, State(create_shared_state())).await.unwrap();
+        let response = serde_json::to_value(response.0).unwrap();
+
+        assert_eq!(response["query"], "kubectl");
+        assert_eq!(response["results"][0]["slug"], "kubernetes");
+        assert_eq!(response["results"][0]["snippet"][1]["highlight"], true);
+    }
+}
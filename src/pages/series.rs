@@ -0,0 +1,218 @@
+use super::{Publication, PublishedPage, Series};
+use crate::{AppError, SharedState, templates::Head};
+use axum::{
+    extract::{Path, State},
+    response::Html,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Link {
+    title: String,
+    slug: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Part {
+    title: String,
+    slug: String,
+    current: bool,
+}
+
+/// The series box rendered on each post that belongs to a series.
+#[derive(Serialize, Debug)]
+pub struct Navigation {
+    name: String,
+    slug: String,
+    position: usize,
+    count: usize,
+    parts: Vec<Part>,
+    previous: Option<Link>,
+    next: Option<Link>,
+}
+
+impl Navigation {
+    pub fn new(publication: &Publication, series: &Series, slug: &str) -> Option<Self> {
+        let pages: Vec<&PublishedPage> = publication.pages_at(&series.pages).collect();
+        let position = pages
+            .iter()
+            .position(|page| page.frontmatter.slug == slug)?;
+
+        let link = |index: usize| {
+            pages.get(index).map(|page| Link {
+                title: page.frontmatter.title.clone(),
+                slug: page.frontmatter.slug.clone(),
+            })
+        };
+
+        Some(Navigation {
+            name: series.name.clone(),
+            slug: series.slug.clone(),
+            position: position + 1,
+            count: pages.len(),
+            parts: pages
+                .iter()
+                .map(|page| Part {
+                    title: page.frontmatter.title.clone(),
+                    slug: page.frontmatter.slug.clone(),
+                    current: page.frontmatter.slug == slug,
+                })
+                .collect(),
+            previous: position.checked_sub(1).and_then(link),
+            next: link(position + 1),
+        })
+    }
+}
+
+/// The context of `pages/series`.
+#[derive(Serialize, Debug)]
+struct SeriesContext {
+    pages: Vec<Link>,
+}
+
+pub async fn show(
+    Path(slug): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Html<String>, AppError> {
+    let publication = state.published_pages.snapshot();
+    let series = publication
+        .series()
+        .iter()
+        .find(|series| series.slug == slug)
+        .ok_or(AppError::PageNotFound)?;
+
+    let pages: Vec<Link> = publication
+        .pages_at(&series.pages)
+        .map(|page| Link {
+            title: page.frontmatter.title.clone(),
+            slug: page.frontmatter.slug.clone(),
+        })
+        .collect();
+
+    let description = publication
+        .pages_at(&series.pages)
+        .next()
+        .map(|page| page.frontmatter.description.clone())
+        .unwrap_or_default();
+
+    let head = Head {
+        title: series.name.clone(),
+        description,
+        canonical_url: state.site.url(&format!("/series/{slug}")),
+    };
+
+    let rendered =
+        state
+            .templates
+            .load()
+            .render_page("pages/series", head, &SeriesContext { pages })?;
+
+    Ok(Html(rendered))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Navigation, show};
+    use crate::pages::{Content, Frontmatter, Publication, PublishedPage, test_support::state};
+    use axum::{
+        extract::{Path, State},
+        response::IntoResponse,
+    };
+    use chrono::Utc;
+
+    fn create_page(slug: &str, title: &str, series_order: u32) -> PublishedPage {
+        PublishedPage {
+            path: slug.to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                description: format!("{title} description"),
+                preview: format!("{title} preview"),
+                published_at: Some(Utc::now()),
+                series: Some("Mail server".to_string()),
+                series_order: Some(series_order),
+                slug: slug.to_string(),
+                title: title.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn create_publication() -> Publication {
+        let content = Content {
+            published: vec![
+                create_page("part-3", "Part 3", 3),
+                create_page("part-2", "Part 2", 2),
+                create_page("part-1", "Part 1", 1),
+            ],
+            ..Default::default()
+        };
+
+        Publication::new(content, Utc::now())
+    }
+
+    fn navigation(slug: &str) -> Option<Navigation> {
+        let publication = create_publication();
+        Navigation::new(&publication, &publication.series()[0], slug)
+    }
+
+    #[test]
+    fn test_navigation_middle_part() {
+        let navigation = navigation("part-2").unwrap();
+
+        assert_eq!(navigation.position, 2);
+        assert_eq!(navigation.count, 3);
+        assert_eq!(navigation.previous.unwrap().slug, "part-1");
+        assert_eq!(navigation.next.unwrap().slug, "part-3");
+        assert!(navigation.parts[1].current);
+    }
+
+    #[test]
+    fn test_navigation_first_and_last_parts() {
+        let first = navigation("part-1").unwrap();
+        assert!(first.previous.is_none());
+        assert_eq!(first.next.unwrap().slug, "part-2");
+
+        let last = navigation("part-3").unwrap();
+        assert_eq!(last.previous.unwrap().slug, "part-2");
+        assert!(last.next.is_none());
+    }
+
+    #[test]
+    fn test_navigation_page_outside_series() {
+        assert!(navigation("other").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_show_lists_parts_in_order() {
+        let pages = vec![
+            create_page("part-2", "Part 2", 2),
+            create_page("part-1", "Part 1", 1),
+        ];
+        let state = state(pages, Default::default());
+
+        let response = show(Path("mail-server".to_string()), State(state))
+            .await
+            .unwrap()
+            .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        let part_1 = body.find("href=\"/post/part-1\"").unwrap();
+        let part_2 = body.find("href=\"/post/part-2\"").unwrap();
+        assert!(part_1 < part_2);
+    }
+
+    #[tokio::test]
+    async fn test_show_unknown_series() {
+        let state = state(vec![create_page("part-1", "Part 1", 1)], Default::default());
+
+        let result = show(Path("unknown".to_string()), State(state)).await;
+        assert!(result.is_err());
+    }
+}
@@ -1,7 +1,8 @@
-use crate::{AppError, SharedState};
+use super::cache::Cached;
+use crate::{AppError, SharedState, site::CacheRoute};
 use anyhow::{Context, anyhow};
-use axum::extract::State;
 use axum::http::{StatusCode, header};
+use axum::{Extension, extract::State};
 use axum::{body::Body, http::HeaderValue, response::IntoResponse};
 use chrono::Utc;
 use std::sync::Arc;
@@ -10,7 +11,8 @@ use xml_builder::{XMLBuilder, XMLElement, XMLVersion};
 pub async fn build_response(
     State(state): State<Arc<SharedState>>,
 ) -> Result<impl IntoResponse, AppError> {
-    let published_pages = state.published_pages.get_all()?;
+    let publication = state.published_pages.snapshot();
+    let published_pages = publication.pages();
 
     let mut xml = XMLBuilder::new()
         .version(XMLVersion::XML1_1)
@@ -20,20 +22,17 @@ pub async fn build_response(
     let mut urlset = XMLElement::new("urlset");
     urlset.add_attribute("xmlns", "http://www.sitemaps.org/schemas/sitemap/0.9");
 
-    for (current_index, page) in published_pages.into_iter().enumerate() {
+    for (current_index, page) in published_pages.iter().enumerate() {
         let mut url = XMLElement::new("url");
         let mut loc = XMLElement::new("loc");
 
         // First page is treated as the homepage
         if current_index == 0 {
-            loc.add_text("https://corybuecker.com".to_string())
+            loc.add_text(state.site.base_url().to_string())
                 .map_err(|e| anyhow!("Failed to add homepage URL: {}", e))?;
         } else {
-            loc.add_text(format!(
-                "https://corybuecker.com/post/{}",
-                page.frontmatter.slug
-            ))
-            .map_err(|e| anyhow!("Failed to add page URL: {}", e))?;
+            loc.add_text(state.site.url(&format!("/post/{}", page.frontmatter.slug)))
+                .map_err(|e| anyhow!("Failed to add page URL: {}", e))?;
         }
 
         url.add_child(loc)
@@ -74,6 +73,7 @@ pub async fn build_response(
             header::CONTENT_TYPE,
             HeaderValue::from_static("application/xml"),
         )],
+        Extension(Cached::new(CacheRoute::Sitemap, publication.updated_at())),
         Body::from(xml_string),
     )
         .into_response();
@@ -86,43 +86,15 @@ mod tests {
     use super::build_response;
     use crate::{
         SharedState,
-        pages::{Frontmatter, PublicationManager, PublishedPage},
+        pages::{Frontmatter, PublishedPage, test_support::state},
+        site::SiteConfig,
     };
-    use anyhow::Result;
     use axum::{extract::State, http::StatusCode, response::IntoResponse};
     use chrono::{DateTime, Utc};
-    use std::{future::Future, pin::Pin, sync::Arc};
+    use std::sync::Arc;
 
-    struct MockPublishedPages {
-        pages: Vec<PublishedPage>,
-    }
-
-    impl PublicationManager for MockPublishedPages {
-        fn get_all(&self) -> Result<Vec<PublishedPage>> {
-            Ok(self.pages.clone())
-        }
-
-        fn publish<'f>(
-            &'f mut self,
-        ) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>> {
-            Box::pin(async move { Ok(self.pages.len()) })
-        }
-
-        fn read<'f>(
-            &'f self,
-            _path: &'f str,
-        ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + Sync + 'f>> {
-            Box::pin(async move { Ok("this is the page content".to_string()) })
-        }
-    }
-
-    async fn create_shared_state(pages: Vec<PublishedPage>) -> Arc<SharedState> {
-        let mock_pages = MockPublishedPages { pages };
-        let renderer = rust_web_common::templating::Renderer::new("templates".to_string()).unwrap();
-        Arc::new(SharedState {
-            renderer,
-            published_pages: Box::new(mock_pages),
-        })
+    fn site(base_url: &str) -> SiteConfig {
+        SiteConfig::new(base_url).unwrap()
     }
 
     fn create_page(
@@ -135,6 +107,9 @@ mod tests {
     ) -> PublishedPage {
         PublishedPage {
             path: path.to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
             published_at,
             frontmatter: Frontmatter {
                 description: description.to_string(),
@@ -143,6 +118,7 @@ mod tests {
                 revised_at,
                 slug: slug.to_string(),
                 title: title.to_string(),
+                ..Default::default()
             },
         }
     }
@@ -185,7 +161,7 @@ mod tests {
             ),
         ];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, site("https://corybuecker.com"));
         let (body_string, status) = execute_request_and_get_body(state).await;
 
         // Check status
@@ -207,6 +183,22 @@ mod tests {
         assert!(body_string.contains("2023-01-01T00:00:00+00:00")); // published date
     }
 
+    #[tokio::test]
+    async fn test_build_response_uses_configured_base_url() {
+        let published_date = Utc::now();
+        let pages = vec![
+            create_page("home", "home", "Home", "Home", published_date, None),
+            create_page("page1", "page1", "Page 1", "Page 1", published_date, None),
+        ];
+
+        let state = state(pages, site("https://staging.example.com/"));
+        let (body_string, _) = execute_request_and_get_body(state).await;
+
+        assert!(body_string.contains("<loc>https://staging.example.com</loc>"));
+        assert!(body_string.contains("<loc>https://staging.example.com/post/page1</loc>"));
+        assert!(!body_string.contains("corybuecker.com"));
+    }
+
     #[tokio::test]
     async fn test_build_response_uses_revised_date_when_available() {
         let published_date = DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z")
@@ -225,7 +217,7 @@ mod tests {
             Some(revised_date),
         )];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, site("https://corybuecker.com"));
         let (body_string, _) = execute_request_and_get_body(state).await;
 
         // Should use the revised date, not the published date
@@ -248,7 +240,7 @@ mod tests {
             None,
         )];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, site("https://corybuecker.com"));
         let (body_string, _) = execute_request_and_get_body(state).await;
 
         // Should use the published date
@@ -259,6 +251,9 @@ mod tests {
     async fn test_build_response_no_dates_uses_current_time() {
         let pages = vec![PublishedPage {
             path: "test-no-dates".to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
             published_at: Utc::now(),
             frontmatter: Frontmatter {
                 description: "No dates page description".to_string(),
@@ -267,10 +262,11 @@ mod tests {
                 revised_at: None,
                 slug: "no-dates".to_string(),
                 title: "No Dates Page".to_string(),
+                ..Default::default()
             },
         }];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, site("https://corybuecker.com"));
         let (body_string, _) = execute_request_and_get_body(state).await;
 
         // Should contain some valid RFC3339 timestamp (current time)
@@ -280,7 +276,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_build_response_empty_pages() {
-        let state = create_shared_state(vec![]).await;
+        let state = state(vec![], site("https://corybuecker.com"));
         let (body_string, _) = execute_request_and_get_body(state).await;
 
         // Should still be valid XML with empty urlset
@@ -320,7 +316,7 @@ mod tests {
             ),
         ];
 
-        let state = create_shared_state(pages).await;
+        let state = state(pages, site("https://corybuecker.com"));
         let (body_string, _) = execute_request_and_get_body(state).await;
 
         // First page should be homepage
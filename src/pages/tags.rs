@@ -0,0 +1,173 @@
+use crate::{AppError, SharedState, templates::Head};
+use axum::{
+    extract::{Path, State},
+    response::Html,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Serialize, Debug)]
+struct Link {
+    title: String,
+    slug: String,
+}
+
+#[derive(Serialize, Debug)]
+struct TagSummary {
+    name: String,
+    slug: String,
+    count: usize,
+}
+
+/// The context of `pages/tags`.
+#[derive(Serialize, Debug)]
+struct TagsContext {
+    tags: Vec<TagSummary>,
+}
+
+/// The context of `pages/tag`.
+#[derive(Serialize, Debug)]
+struct TagContext {
+    pages: Vec<Link>,
+}
+
+pub async fn index(State(state): State<Arc<SharedState>>) -> Result<Html<String>, AppError> {
+    let tags: Vec<TagSummary> = state
+        .published_pages
+        .snapshot()
+        .tags()
+        .iter()
+        .map(|tag| TagSummary {
+            count: tag.pages.len(),
+            name: tag.name.clone(),
+            slug: tag.slug.clone(),
+        })
+        .collect();
+
+    let head = Head {
+        title: "Tags".to_string(),
+        description: "Every topic covered on the blog.".to_string(),
+        canonical_url: state.site.url("/tags"),
+    };
+
+    let rendered = state
+        .templates
+        .load()
+        .render_page("pages/tags", head, &TagsContext { tags })?;
+
+    Ok(Html(rendered))
+}
+
+pub async fn show(
+    Path(slug): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Html<String>, AppError> {
+    let publication = state.published_pages.snapshot();
+    let tag = publication
+        .tags()
+        .iter()
+        .find(|tag| tag.slug == slug)
+        .ok_or(AppError::PageNotFound)?;
+
+    let pages: Vec<Link> = publication
+        .pages_at(&tag.pages)
+        .map(|page| Link {
+            title: page.frontmatter.title.clone(),
+            slug: page.frontmatter.slug.clone(),
+        })
+        .collect();
+
+    let head = Head {
+        title: format!("Posts tagged {}", tag.name),
+        description: format!("Posts tagged {}.", tag.name),
+        canonical_url: state.site.url(&format!("/tags/{}", tag.slug)),
+    };
+
+    let rendered = state
+        .templates
+        .load()
+        .render_page("pages/tag", head, &TagContext { pages })?;
+
+    Ok(Html(rendered))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index, show};
+    use crate::pages::{Frontmatter, PublishedPage, test_support::state};
+    use axum::{
+        extract::{Path, State},
+        response::IntoResponse,
+    };
+    use chrono::Utc;
+
+    fn create_page(slug: &str, title: &str, tags: &[&str]) -> PublishedPage {
+        PublishedPage {
+            path: slug.to_string(),
+            content: "this is the page content".to_string(),
+            toc: Vec::new(),
+            reading_time: Default::default(),
+            published_at: Utc::now(),
+            frontmatter: Frontmatter {
+                description: format!("{title} description"),
+                preview: format!("{title} preview"),
+                published_at: Some(Utc::now()),
+                slug: slug.to_string(),
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                title: title.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    async fn body_string(response: impl IntoResponse) -> String {
+        let body = axum::body::to_bytes(response.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_index_lists_tags_with_counts() {
+        let pages = vec![
+            create_page("page1", "Page 1", &["Kubernetes", "Mail server"]),
+            create_page("page2", "Page 2", &["Kubernetes"]),
+        ];
+        let state = state(pages, Default::default());
+
+        let body = body_string(index(State(state)).await.unwrap()).await;
+
+        assert!(body.contains("href=\"/tags/kubernetes\""));
+        assert!(body.contains("href=\"/tags/mail-server\""));
+        assert!(body.contains("Mail server"));
+    }
+
+    #[tokio::test]
+    async fn test_show_lists_matching_pages() {
+        let pages = vec![
+            create_page("page1", "Page 1", &["Kubernetes", "Mail server"]),
+            create_page("page2", "Page 2", &["Kubernetes"]),
+            create_page("page3", "Page 3", &["Klipper"]),
+        ];
+        let state = state(pages, Default::default());
+
+        let response = show(Path("mail-server".to_string()), State(state))
+            .await
+            .unwrap();
+        let body = body_string(response).await;
+
+        assert!(body.contains("Posts tagged Mail server"));
+        assert!(body.contains("href=\"/post/page1\""));
+        assert!(!body.contains("href=\"/post/page2\""));
+        assert!(!body.contains("href=\"/post/page3\""));
+    }
+
+    #[tokio::test]
+    async fn test_show_unknown_tag() {
+        let pages = vec![create_page("page1", "Page 1", &["Kubernetes"])];
+        let state = state(pages, Default::default());
+
+        let result = show(Path("elixir".to_string()), State(state)).await;
+        assert!(result.is_err());
+    }
+}
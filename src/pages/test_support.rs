@@ -0,0 +1,55 @@
+use super::{Content, Publication, PublicationManager, PublishedPage};
+use crate::{SharedState, site::SiteConfig, templates::Templates};
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use chrono::Utc;
+use std::{collections::BTreeMap, future::Future, pin::Pin, sync::Arc};
+
+/// Publishes a fixed set of pages, drafts and redirects. Every snapshot is built afresh, so
+/// scheduled pages go live as soon as their time passes.
+#[derive(Default)]
+pub struct MockPublishedPages {
+    pub pages: Vec<PublishedPage>,
+    pub drafts: Vec<PublishedPage>,
+    pub redirects: BTreeMap<String, String>,
+}
+
+impl PublicationManager for MockPublishedPages {
+    fn snapshot(&self) -> Arc<Publication> {
+        let content = Content {
+            published: self.pages.clone(),
+            drafts: self.drafts.clone(),
+            redirects: self.redirects.clone(),
+        };
+        Arc::new(Publication::new(content, Utc::now()))
+    }
+
+    fn publish<'f>(&'f self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + Sync + 'f>> {
+        Box::pin(async move { Ok(self.pages.len()) })
+    }
+}
+
+/// The state handlers see, serving `published_pages` with the repository's templates and no
+/// preview signer.
+pub fn shared_state(published_pages: MockPublishedPages, site: SiteConfig) -> SharedState {
+    let templates = Templates::load(&site, Default::default()).unwrap();
+
+    SharedState {
+        templates: ArcSwap::from_pointee(templates),
+        assets: Default::default(),
+        published_pages: Box::new(published_pages),
+        preview_signer: None,
+        site,
+        og_cards: Default::default(),
+    }
+}
+
+/// Shared state publishing `pages`.
+pub fn state(pages: Vec<PublishedPage>, site: SiteConfig) -> Arc<SharedState> {
+    let published_pages = MockPublishedPages {
+        pages,
+        ..Default::default()
+    };
+
+    Arc::new(shared_state(published_pages, site))
+}
@@ -0,0 +1,194 @@
+use crate::{SharedState, assets::AssetManifest, site::SiteConfig, templates::Templates};
+use notify::{Event, RecursiveMode, Watcher, recommended_watcher};
+use std::{
+    path::{self, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::mpsc::unbounded_channel, task::spawn_blocking, time::timeout};
+use tracing::{error, info, instrument};
+
+/// Editors often write a file in several steps, so wait for events to settle before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// What a settled batch of file events touched, so that a reload only redoes what it must.
+#[derive(Default, Debug, PartialEq)]
+struct Changes {
+    /// Anything in the static directory, whose manifest is slow to rebuild.
+    assets: bool,
+    /// Content, templates or redirects.
+    pages: bool,
+}
+
+/// The watched paths, made absolute since that is how the watcher reports events.
+struct Watched {
+    content: PathBuf,
+    templates: PathBuf,
+    assets: PathBuf,
+    redirects: PathBuf,
+}
+
+impl Watched {
+    fn new(site: &SiteConfig) -> Self {
+        let absolute = |path: &Path| path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+        Self {
+            content: absolute(site.content_directory()),
+            templates: absolute(site.templates_directory()),
+            assets: absolute(site.static_directory()),
+            redirects: absolute(site.redirects_file()),
+        }
+    }
+
+    /// The redirects file is watched through its directory, which reports its neighbors too.
+    fn add(&self, changes: &mut Changes, paths: &[PathBuf]) {
+        for path in paths {
+            if path.starts_with(&self.assets) {
+                changes.assets = true;
+            } else if path.starts_with(&self.content)
+                || path.starts_with(&self.templates)
+                || path.file_name() == self.redirects.file_name()
+                    && path.parent() == self.redirects.parent()
+            {
+                changes.pages = true;
+            }
+        }
+    }
+}
+
+/// Watches the content, template and static directories and the redirects file, and republishes
+/// whenever they change. Requests keep being served from the previous pages, templates and assets
+/// until a reload succeeds, and a failed reload leaves them in place.
+pub async fn watch(state: Arc<SharedState>) {
+    let (sender, mut receiver) = unbounded_channel();
+
+    let watcher = recommended_watcher(move |event: notify::Result<Event>| match event {
+        Ok(event) if !event.kind.is_access() => {
+            let _ = sender.send(event.paths);
+        }
+        Ok(_) => {}
+        Err(err) => error!("file watcher error: {}", err),
+    });
+
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            error!("could not start file watcher: {}", err);
+            return;
+        }
+    };
+
+    let watched = Watched::new(&state.site);
+
+    // Editors save by renaming over the file, which a watch on the file itself loses, and the
+    // file may not exist yet, so its directory is watched instead.
+    let redirects_directory = watched.redirects.parent().unwrap_or(Path::new("/"));
+
+    for (path, mode) in [
+        (watched.content.as_path(), RecursiveMode::Recursive),
+        (watched.templates.as_path(), RecursiveMode::Recursive),
+        (watched.assets.as_path(), RecursiveMode::Recursive),
+        (redirects_directory, RecursiveMode::NonRecursive),
+    ] {
+        if let Err(err) = watcher.watch(path, mode) {
+            error!("could not watch {}: {}", path.display(), err);
+        }
+    }
+
+    while let Some(paths) = receiver.recv().await {
+        let mut changes = Changes::default();
+        watched.add(&mut changes, &paths);
+        while let Ok(Some(paths)) = timeout(DEBOUNCE, receiver.recv()).await {
+            watched.add(&mut changes, &paths);
+        }
+
+        if changes == Changes::default() {
+            continue;
+        }
+
+        reload(&state, changes).await;
+    }
+}
+
+#[instrument(skip(state))]
+async fn reload(state: &SharedState, changes: Changes) {
+    if changes.assets {
+        // Compressing the assets is CPU-bound, so it stays off the request threads.
+        let directory = state.site.static_directory().to_path_buf();
+        match spawn_blocking(move || AssetManifest::build(&directory)).await {
+            Ok(Ok(assets)) => state.assets.store(Arc::new(assets)),
+            Ok(Err(err)) => error!("could not reload static assets, keeping previous: {}", err),
+            Err(err) => error!("could not reload static assets, keeping previous: {}", err),
+        }
+    }
+
+    // Templates embed asset digests, so they are reloaded after either kind of change.
+    match Templates::load(&state.site, state.assets.load_full()) {
+        Ok(templates) => {
+            state.templates.store(Arc::new(templates));
+            state.og_cards.clear();
+        }
+        Err(err) => error!("could not reload templates, keeping previous: {}", err),
+    }
+
+    if !changes.pages {
+        return;
+    }
+
+    match state.published_pages.publish().await {
+        Ok(count) => info!(count, "reloaded published pages"),
+        Err(err) => error!("could not reload pages, keeping previous: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Changes, Watched};
+    use std::path::PathBuf;
+
+    fn watched() -> Watched {
+        Watched {
+            content: PathBuf::from("/srv/blog/content"),
+            templates: PathBuf::from("/srv/blog/templates"),
+            assets: PathBuf::from("/srv/blog/static"),
+            redirects: PathBuf::from("/srv/blog/redirects.toml"),
+        }
+    }
+
+    fn changes(paths: &[&str]) -> Changes {
+        let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        let mut changes = Changes::default();
+        watched().add(&mut changes, &paths);
+        changes
+    }
+
+    #[test]
+    fn test_changes_separate_assets_from_pages() {
+        assert_eq!(
+            changes(&["/srv/blog/static/app.css"]),
+            Changes {
+                assets: true,
+                pages: false
+            }
+        );
+        assert_eq!(
+            changes(&["/srv/blog/static/app.css", "/srv/blog/content/post.md"]),
+            Changes {
+                assets: true,
+                pages: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_changes_filter_redirects_directory() {
+        assert_eq!(
+            changes(&["/srv/blog/.redirects.toml.swp", "/srv/blog/redirects.toml"]),
+            Changes {
+                assets: false,
+                pages: true
+            }
+        );
+        assert_eq!(changes(&["/srv/blog/Cargo.toml"]), Changes::default());
+    }
+}
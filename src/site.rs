@@ -0,0 +1,485 @@
+use crate::pages::DEFAULT_WORDS_PER_MINUTE;
+use anyhow::{Context, Result, anyhow};
+use axum::http::{HeaderValue, Uri};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    io::ErrorKind,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const SITE_CONFIG_FILE: &str = "site.toml";
+const DEFAULT_BIND_ADDRESS: ([u8; 4], u16) = ([0, 0, 0, 0], 8000);
+const DEFAULT_BASE_URL: &str = "https://corybuecker.com";
+const DEFAULT_POSTS_PER_PAGE: usize = 10;
+const DEFAULT_TITLE: &str = "Cory Buecker";
+const DEFAULT_DESCRIPTION: &str = "Posts by Cory Buecker";
+
+/// What the homepage shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HomeMode {
+    /// The newest post in full, followed by links to older posts.
+    #[default]
+    Latest,
+    /// A card per post with its preview, paginated through `/page/{n}`.
+    Cards,
+}
+
+impl FromStr for HomeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "latest" => Ok(HomeMode::Latest),
+            "cards" => Ok(HomeMode::Cards),
+            _ => Err(anyhow!(
+                "invalid home mode {value:?}: expected latest or cards"
+            )),
+        }
+    }
+}
+
+/// Icons the layout can draw for a navigation link.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NavIcon {
+    Github,
+    Linkedin,
+}
+
+/// A link in the site navigation. Links with an icon show it in place of the label, which then
+/// becomes the link's accessible name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NavLink {
+    pub label: String,
+    pub href: String,
+    #[serde(default)]
+    pub icon: Option<NavIcon>,
+    /// Marks the link as another profile of the author with `rel="me"`, both in the navigation and
+    /// in the `<head>` of every page.
+    #[serde(default)]
+    pub rel_me: bool,
+}
+
+impl NavLink {
+    fn new(label: &str, href: &str, icon: Option<NavIcon>, rel_me: bool) -> Self {
+        Self {
+            label: label.to_string(),
+            href: href.to_string(),
+            icon,
+            rel_me,
+        }
+    }
+}
+
+/// Rendered pages whose `Cache-Control` can be configured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheRoute {
+    /// `/post/{slug}`.
+    Post,
+    /// The homepage and its later pages.
+    Home,
+    /// `/sitemap.xml`.
+    Sitemap,
+}
+
+/// The `Cache-Control` sent with each cacheable page, from the `[cache_control]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheControl {
+    post: String,
+    home: String,
+    sitemap: String,
+}
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        Self {
+            post: "public, max-age=300".to_string(),
+            home: "public, max-age=60".to_string(),
+            sitemap: "public, max-age=3600".to_string(),
+        }
+    }
+}
+
+impl CacheControl {
+    pub fn get(&self, route: CacheRoute) -> &str {
+        match route {
+            CacheRoute::Post => &self.post,
+            CacheRoute::Home => &self.home,
+            CacheRoute::Sitemap => &self.sitemap,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (key, value) in [
+            ("post", &self.post),
+            ("home", &self.home),
+            ("sitemap", &self.sitemap),
+        ] {
+            HeaderValue::from_str(value)
+                .with_context(|| format!("invalid cache_control.{key} {value:?}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The parts of the configuration that templates see, as `site`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Identity {
+    title: String,
+    author: String,
+    description: String,
+    nav: Vec<NavLink>,
+}
+
+/// Settings shared by every handler. Read from `site.toml`, or the file named by `SITE_CONFIG`,
+/// with environment variables taking precedence for everything that differs between deployments
+/// or instances, so that each can set its own from a ConfigMap.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    bind_address: SocketAddr,
+    base_url: String,
+    content_directory: PathBuf,
+    templates_directory: PathBuf,
+    static_directory: PathBuf,
+    /// Optional site-wide redirects, see `pages::redirects`.
+    redirects_file: PathBuf,
+    home_mode: HomeMode,
+    posts_per_page: usize,
+    words_per_minute: usize,
+    cache_control: CacheControl,
+    title: String,
+    author: String,
+    description: String,
+    nav: Vec<NavLink>,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(DEFAULT_BIND_ADDRESS),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            content_directory: PathBuf::from("content"),
+            templates_directory: PathBuf::from("templates"),
+            static_directory: PathBuf::from("static"),
+            redirects_file: PathBuf::from("redirects.toml"),
+            home_mode: HomeMode::default(),
+            posts_per_page: DEFAULT_POSTS_PER_PAGE,
+            words_per_minute: DEFAULT_WORDS_PER_MINUTE,
+            cache_control: CacheControl::default(),
+            title: DEFAULT_TITLE.to_string(),
+            author: DEFAULT_TITLE.to_string(),
+            description: DEFAULT_DESCRIPTION.to_string(),
+            nav: vec![
+                NavLink::new(
+                    "GitHub",
+                    "https://github.com/corybuecker",
+                    Some(NavIcon::Github),
+                    true,
+                ),
+                NavLink::new(
+                    "Projects",
+                    "https://projects.corybuecker.dev/corybuecker",
+                    None,
+                    true,
+                ),
+                NavLink::new(
+                    "LinkedIn",
+                    "https://www.linkedin.com/in/corybuecker",
+                    Some(NavIcon::Linkedin),
+                    false,
+                ),
+                NavLink::new("Search", "/search", None, false),
+            ],
+        }
+    }
+}
+
+impl SiteConfig {
+    pub fn new(base_url: &str) -> Result<Self> {
+        Ok(Self {
+            base_url: normalize_base_url(base_url)?,
+            ..Default::default()
+        })
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = env::var("SITE_CONFIG").unwrap_or_else(|_| SITE_CONFIG_FILE.to_string());
+
+        let config = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str::<SiteConfig>(&contents)
+                .map_err(|e| anyhow!("{path}: invalid site configuration: {e}"))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => SiteConfig::default(),
+            Err(err) => return Err(err).with_context(|| format!("could not read {path}")),
+        };
+
+        config.with_overrides(|key| env::var(key).ok().filter(|value| !value.is_empty()))
+    }
+
+    /// Applies environment overrides, looked up through `var`, and validates the result.
+    fn with_overrides(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        if let Some(bind_address) = var("BIND_ADDRESS") {
+            self.bind_address = bind_address
+                .parse()
+                .with_context(|| format!("invalid BIND_ADDRESS {bind_address:?}"))?;
+        }
+        if let Some(base_url) = var("BASE_URL") {
+            self.base_url = base_url;
+        }
+        for (key, directory) in [
+            ("CONTENT_DIRECTORY", &mut self.content_directory),
+            ("TEMPLATES_DIRECTORY", &mut self.templates_directory),
+            ("STATIC_DIRECTORY", &mut self.static_directory),
+            ("REDIRECTS_FILE", &mut self.redirects_file),
+        ] {
+            if let Some(path) = var(key) {
+                *directory = PathBuf::from(path);
+            }
+        }
+        if let Some(home_mode) = var("HOME_MODE") {
+            self.home_mode = home_mode.parse()?;
+        }
+        if let Some(posts_per_page) = var("POSTS_PER_PAGE") {
+            self.posts_per_page = posts_per_page
+                .parse()
+                .with_context(|| format!("invalid POSTS_PER_PAGE {posts_per_page:?}"))?;
+        }
+        if let Some(words_per_minute) = var("WORDS_PER_MINUTE") {
+            self.words_per_minute = words_per_minute
+                .parse()
+                .with_context(|| format!("invalid WORDS_PER_MINUTE {words_per_minute:?}"))?;
+        }
+
+        if self.posts_per_page == 0 {
+            return Err(anyhow!("posts_per_page must be at least 1"));
+        }
+        if self.words_per_minute == 0 {
+            return Err(anyhow!("words_per_minute must be at least 1"));
+        }
+        self.cache_control.validate()?;
+        self.base_url = normalize_base_url(&self.base_url)?;
+
+        Ok(self)
+    }
+
+    /// The site identity, which every page shows.
+    pub fn identity(&self) -> Identity {
+        Identity {
+            title: self.title.clone(),
+            author: self.author.clone(),
+            description: self.description.clone(),
+            nav: self.nav.clone(),
+        }
+    }
+
+    pub fn bind_address(&self) -> SocketAddr {
+        self.bind_address
+    }
+
+    /// The canonical origin, without a trailing slash, e.g. `https://corybuecker.com`.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn content_directory(&self) -> &Path {
+        &self.content_directory
+    }
+
+    pub fn templates_directory(&self) -> &Path {
+        &self.templates_directory
+    }
+
+    pub fn static_directory(&self) -> &Path {
+        &self.static_directory
+    }
+
+    pub fn redirects_file(&self) -> &Path {
+        &self.redirects_file
+    }
+
+    pub fn home_mode(&self) -> HomeMode {
+        self.home_mode
+    }
+
+    pub fn posts_per_page(&self) -> usize {
+        self.posts_per_page
+    }
+
+    pub fn words_per_minute(&self) -> usize {
+        self.words_per_minute
+    }
+
+    pub fn cache_control(&self) -> &CacheControl {
+        &self.cache_control
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Joins an absolute path such as `/post/slug` onto the base URL.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+}
+
+fn normalize_base_url(base_url: &str) -> Result<String> {
+    let uri: Uri = base_url
+        .parse()
+        .with_context(|| format!("invalid base URL {base_url:?}"))?;
+
+    let valid = matches!(uri.scheme_str(), Some("http" | "https"))
+        && uri.authority().is_some()
+        && matches!(uri.path(), "" | "/")
+        && uri.query().is_none();
+
+    if !valid {
+        return Err(anyhow!(
+            "invalid base URL {base_url:?}: expected an http or https origin"
+        ));
+    }
+
+    Ok(base_url.trim_end_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheRoute, HomeMode, NavIcon, SiteConfig};
+    use std::path::Path;
+
+    #[test]
+    fn test_base_url_trailing_slash_is_trimmed() {
+        let site = SiteConfig::new("https://staging.corybuecker.com/").unwrap();
+
+        assert_eq!(site.base_url(), "https://staging.corybuecker.com");
+        assert_eq!(
+            site.url("/post/slug"),
+            "https://staging.corybuecker.com/post/slug"
+        );
+    }
+
+    #[test]
+    fn test_base_url_requires_http_origin() {
+        assert!(SiteConfig::new("corybuecker.com").is_err());
+        assert!(SiteConfig::new("ftp://corybuecker.com").is_err());
+        assert!(SiteConfig::new("https://corybuecker.com?q=1").is_err());
+        // The router is mounted at `/`, so links under a path prefix would never be served.
+        assert!(SiteConfig::new("https://corybuecker.com/blog").is_err());
+        assert!(SiteConfig::new("http://localhost:8000/").is_ok());
+    }
+
+    #[test]
+    fn test_parse_site_file() {
+        let site: SiteConfig = toml::from_str("base_url = \"https://example.com\"").unwrap();
+        assert_eq!(site.base_url(), "https://example.com");
+
+        assert!(toml::from_str::<SiteConfig>("theme = \"Unknown\"").is_err());
+
+        let site: SiteConfig = toml::from_str("home_mode = \"cards\"\nposts_per_page = 5").unwrap();
+        assert_eq!(site.home_mode(), HomeMode::Cards);
+        assert_eq!(site.posts_per_page(), 5);
+        assert_eq!(site.base_url(), "https://corybuecker.com");
+        assert_eq!(site.bind_address().to_string(), "0.0.0.0:8000");
+        assert_eq!(site.content_directory(), Path::new("content"));
+    }
+
+    #[test]
+    fn test_parse_site_identity() {
+        let site: SiteConfig = toml::from_str(
+            r#"
+            title = "Example"
+            author = "Jane Example"
+
+            [[nav]]
+            label = "GitHub"
+            href = "https://github.com/example"
+            icon = "github"
+            rel_me = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(site.title(), "Example");
+        assert_eq!(site.author(), "Jane Example");
+        assert_eq!(site.description(), "Posts by Cory Buecker");
+        assert_eq!(site.nav.len(), 1);
+        assert_eq!(site.nav[0].icon, Some(NavIcon::Github));
+        assert!(site.nav[0].rel_me);
+
+        assert!(toml::from_str::<SiteConfig>("[[nav]]\nlabel = \"Home\"").is_err());
+        assert!(
+            toml::from_str::<SiteConfig>("[[nav]]\nlabel = \"A\"\nhref = \"/\"\nicon = \"x\"")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_environment_overrides_file() {
+        let site: SiteConfig = toml::from_str("base_url = \"https://example.com\"").unwrap();
+        let site = site
+            .with_overrides(|key| match key {
+                "BIND_ADDRESS" => Some("127.0.0.1:9000".to_string()),
+                "BASE_URL" => Some("https://staging.example.com/".to_string()),
+                "CONTENT_DIRECTORY" => Some("/srv/content".to_string()),
+                "TEMPLATES_DIRECTORY" => Some("/srv/templates".to_string()),
+                "HOME_MODE" => Some("cards".to_string()),
+                "POSTS_PER_PAGE" => Some("3".to_string()),
+                "WORDS_PER_MINUTE" => Some("250".to_string()),
+                _ => None,
+            })
+            .unwrap();
+
+        assert_eq!(site.bind_address().to_string(), "127.0.0.1:9000");
+        assert_eq!(site.base_url(), "https://staging.example.com");
+        assert_eq!(site.content_directory(), Path::new("/srv/content"));
+        assert_eq!(site.templates_directory(), Path::new("/srv/templates"));
+        assert_eq!(site.static_directory(), Path::new("static"));
+        assert_eq!(site.home_mode(), HomeMode::Cards);
+        assert_eq!(site.posts_per_page(), 3);
+        assert_eq!(site.words_per_minute(), 250);
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        let invalid = |key: &'static str, value: &'static str| {
+            SiteConfig::default()
+                .with_overrides(|name| (name == key).then(|| value.to_string()))
+                .is_err()
+        };
+
+        assert!(invalid("BIND_ADDRESS", "localhost"));
+        assert!(invalid("HOME_MODE", "grid"));
+        assert!(invalid("POSTS_PER_PAGE", "0"));
+        assert!(invalid("POSTS_PER_PAGE", "ten"));
+        assert!(invalid("WORDS_PER_MINUTE", "0"));
+    }
+
+    #[test]
+    fn test_parse_cache_control() {
+        let site: SiteConfig = toml::from_str("[cache_control]\npost = \"no-cache\"").unwrap();
+        assert_eq!(site.cache_control().get(CacheRoute::Post), "no-cache");
+        assert_eq!(
+            site.cache_control().get(CacheRoute::Sitemap),
+            "public, max-age=3600"
+        );
+
+        let site: SiteConfig = toml::from_str("[cache_control]\nhome = \"max-age=60\\n\"").unwrap();
+        assert!(site.with_overrides(|_| None).is_err());
+        assert!(toml::from_str::<SiteConfig>("[cache_control]\nfeed = \"no-store\"").is_err());
+    }
+}
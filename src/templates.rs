@@ -0,0 +1,236 @@
+use crate::{
+    assets::AssetManifest,
+    site::{Identity, SiteConfig},
+};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use handlebars::{
+    Context, DirectorySourceOptions, Handlebars, Helper, HelperDef, HelperResult, Output,
+    RenderContext, RenderErrorReason,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// The compiled templates. They keep no per-request state: every render is handed a context of
+/// its own, so concurrent requests can never see each other's values or leftovers from an earlier
+/// route.
+pub struct Templates {
+    handlebars: Handlebars<'static>,
+    site: Identity,
+    loaded_at: DateTime<Utc>,
+}
+
+/// What the layout's `<head>` shows for every page.
+#[derive(Serialize, Debug)]
+pub struct Head {
+    pub title: String,
+    pub description: String,
+    pub canonical_url: String,
+}
+
+/// The context of `layout`. The page's own context is flattened in, so partials read their fields
+/// at the top level just as the layout reads `title`.
+#[derive(Serialize)]
+struct Layout<'a, C> {
+    site: &'a Identity,
+    partial: &'a str,
+    #[serde(flatten)]
+    head: Head,
+    #[serde(flatten)]
+    page: &'a C,
+}
+
+impl Templates {
+    /// Compiles every `.hbs` file in the templates directory, named by its path without the
+    /// extension, e.g. `pages/page`. Asset links are digested from `assets`.
+    pub fn load(site: &SiteConfig, assets: Arc<AssetManifest>) -> Result<Self> {
+        let directory = site.templates_directory();
+        let mut options = DirectorySourceOptions::default();
+        options.tpl_extension = ".hbs".to_string();
+
+        let mut handlebars = Handlebars::new();
+        handlebars
+            .register_templates_directory(directory, options)
+            .map_err(|e| anyhow!("could not load templates from {}: {e}", directory.display()))?;
+        handlebars.register_helper("digest_asset", Box::new(DigestAsset { assets }));
+
+        Ok(Self {
+            handlebars,
+            site: site.identity(),
+            loaded_at: Utc::now(),
+        })
+    }
+
+    pub fn site(&self) -> &Identity {
+        &self.site
+    }
+
+    /// When these templates were compiled, which changes every rendered page.
+    pub fn loaded_at(&self) -> DateTime<Utc> {
+        self.loaded_at
+    }
+
+    /// Renders `partial` inside the layout, with `page` as the partial's context.
+    pub fn render_page<C: Serialize>(&self, partial: &str, head: Head, page: &C) -> Result<String> {
+        self.render(
+            "layout",
+            &Layout {
+                site: &self.site,
+                partial,
+                head,
+                page,
+            },
+        )
+    }
+
+    /// Renders a template on its own, such as the 404 page or a sharing card.
+    pub fn render<C: Serialize>(&self, name: &str, context: &C) -> Result<String> {
+        self.handlebars
+            .render(name, context)
+            .map_err(|e| anyhow!("could not render template: {e}"))
+    }
+}
+
+/// `{{digest_asset 'app.css'}}` links to a static asset with a hash of its contents in the query
+/// string, so that browsers fetch it again whenever it changes.
+struct DigestAsset {
+    assets: Arc<AssetManifest>,
+}
+
+impl HelperDef for DigestAsset {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        helper: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let name = helper
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("digest_asset", 0))?;
+
+        out.write(&self.assets.url(name))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Head, Templates};
+    use crate::{assets::AssetManifest, site::SiteConfig};
+    use serde::Serialize;
+    use std::{path::Path, sync::Arc, thread};
+
+    #[derive(Serialize)]
+    struct TagsContext {
+        tags: Vec<Tag>,
+    }
+
+    #[derive(Serialize)]
+    struct Tag {
+        name: String,
+        slug: String,
+        count: usize,
+    }
+
+    fn head(title: &str) -> Head {
+        Head {
+            title: title.to_string(),
+            description: format!("{title} description"),
+            canonical_url: format!("http://localhost:8000/{title}"),
+        }
+    }
+
+    #[test]
+    fn test_render_page_includes_site_and_digests() {
+        let assets = AssetManifest::build(Path::new("static")).unwrap();
+        let templates = Templates::load(&SiteConfig::default(), Arc::new(assets)).unwrap();
+
+        let page = templates
+            .render_page(
+                "pages/tags",
+                head("Tags"),
+                &TagsContext { tags: Vec::new() },
+            )
+            .unwrap();
+
+        assert!(page.contains("<title>Tags &middot; Cory Buecker</title>"));
+        assert!(page.contains("href=\"/assets/favicon.svg?v="));
+        assert!(page.contains("href=\"/assets/app.css"));
+    }
+
+    #[test]
+    fn test_render_page_links_profiles_from_nav() {
+        let render = |site: &SiteConfig| {
+            Templates::load(site, Default::default())
+                .unwrap()
+                .render_page(
+                    "pages/tags",
+                    head("Tags"),
+                    &TagsContext { tags: Vec::new() },
+                )
+                .unwrap()
+        };
+
+        let page = render(&SiteConfig::default());
+        assert!(page.contains("<link rel=\"me\" href=\"https://github.com/corybuecker\" />"));
+        assert!(
+            page.contains(
+                "<link rel=\"me\" href=\"https://projects.corybuecker.dev/corybuecker\" />"
+            )
+        );
+        assert!(!page.contains("<link rel=\"me\" href=\"/search\" />"));
+
+        let site: SiteConfig = toml::from_str(
+            r#"
+            [[nav]]
+            label = "Mastodon"
+            href = "https://example.social/@author"
+            rel_me = true
+            "#,
+        )
+        .unwrap();
+        let page = render(&site);
+        assert!(page.contains("<link rel=\"me\" href=\"https://example.social/@author\" />"));
+        assert!(!page.contains("github.com"));
+    }
+
+    #[test]
+    fn test_concurrent_renders_are_isolated() {
+        let templates =
+            Arc::new(Templates::load(&SiteConfig::default(), Default::default()).unwrap());
+
+        let handles: Vec<_> = (0..8)
+            .map(|index| {
+                let templates = templates.clone();
+                thread::spawn(move || {
+                    let name = format!("tag{index}");
+                    let context = TagsContext {
+                        tags: vec![Tag {
+                            name: name.clone(),
+                            slug: name.clone(),
+                            count: index,
+                        }],
+                    };
+
+                    for _ in 0..20 {
+                        let page = templates
+                            .render_page("pages/tags", head(&name), &context)
+                            .unwrap();
+
+                        assert!(page.contains(&format!("<title>{name} &middot;")));
+                        assert!(page.contains(&format!("href=\"/tags/{name}\"")));
+                        assert_eq!(page.matches("href=\"/tags/").count(), 1);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}